@@ -1,3 +1,11 @@
+use std::env;
+
 fn main() {
-    // No-op, required to set the `OUT_DIR` variable
+    // Besides, the build script is required to set the `OUT_DIR` variable
+
+    // rustup installs the toolchains per host, e.g. `nightly-{host}`
+    println!(
+        "cargo:rustc-env=PTX_BUILDER_HOST={}",
+        env::var("TARGET").unwrap()
+    );
 }
@@ -0,0 +1,299 @@
+#![deny(clippy::pedantic)]
+#![deny(warnings)]
+
+//! `cargo ptx` - build a device crate and inspect its PTX assembly outside of
+//! a host build script.
+
+use std::{path::PathBuf, process::exit};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use ptx_builder::{
+    doctor::{CheckStatus, Doctor},
+    error::Result,
+    prelude::*,
+};
+
+#[derive(Parser)]
+#[command(name = "cargo", bin_name = "cargo")]
+enum Cli {
+    /// Build NVPTX device crates.
+    #[command(version, about)]
+    Ptx(PtxArgs),
+}
+
+#[derive(Args)]
+struct PtxArgs {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Build the device crate and print the path to its PTX assembly.
+    Build(BuildArgs),
+
+    /// Build the device crate and print the files it depends on.
+    Deps(BuildArgs),
+
+    /// Check that the environment is set up for building device crates.
+    Doctor(DoctorArgs),
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Path to the device crate.
+    #[arg(default_value = ".")]
+    path: PathBuf,
+
+    /// Build with the release profile.
+    #[arg(long, conflicts_with = "profile")]
+    release: bool,
+
+    /// Build with the given profile, defaults to the crate's configuration.
+    #[arg(long, value_enum)]
+    profile: Option<ProfileArg>,
+
+    /// Build the library or binary target of a mixed crate.
+    #[arg(long, value_enum)]
+    crate_type: Option<CrateTypeArg>,
+
+    /// Target CPU to generate the PTX assembly for, e.g. `sm_75`.
+    #[arg(long, value_name = "ARCH")]
+    arch: Option<String>,
+
+    /// Comma-separated list of features to activate.
+    #[arg(long, value_delimiter = ',')]
+    features: Option<Vec<String>>,
+
+    /// Rustup toolchain to build with, defaults to the one in `PATH`.
+    #[arg(long)]
+    toolchain: Option<String>,
+
+    /// Directory for the build artifacts, defaults to `target/ptx-builder`
+    /// inside the device crate.
+    #[arg(long, value_name = "DIR")]
+    target_dir: Option<PathBuf>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct DoctorArgs {
+    /// Path to the device crate.
+    #[arg(default_value = ".")]
+    path: PathBuf,
+
+    /// Rustup toolchain to check, defaults to the one in `PATH`.
+    #[arg(long)]
+    toolchain: Option<String>,
+
+    #[command(flatten)]
+    output: OutputArgs,
+}
+
+#[derive(Args)]
+struct OutputArgs {
+    /// Output format of the result.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Human)]
+    message_format: OutputFormat,
+
+    /// Disable colors in the build and error output.
+    #[arg(long)]
+    no_color: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ProfileArg {
+    Debug,
+    Release,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum CrateTypeArg {
+    Lib,
+    Bin,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+/// What the build-based commands print on success.
+#[derive(Clone, Copy)]
+enum Artifact {
+    Assembly,
+    Dependencies,
+}
+
+fn main() {
+    let Cli::Ptx(PtxArgs { command }) = Cli::parse();
+
+    match command {
+        Command::Build(args) => build(Artifact::Assembly, &args),
+        Command::Deps(args) => build(Artifact::Dependencies, &args),
+        Command::Doctor(args) => exit(doctor(&args)),
+    }
+}
+
+fn build(artifact: Artifact, args: &BuildArgs) {
+    if let Err(error) = run(artifact, args) {
+        let output = &args.output;
+        let mut printer = ErrorLogPrinter::print(error);
+
+        if output.no_color || output.message_format == OutputFormat::Json {
+            printer.disable_colors();
+        }
+
+        if output.message_format == OutputFormat::Json {
+            println!(
+                "{}",
+                serde_json::json!({
+                    "reason": "error",
+                    "message": printer.to_string(),
+                })
+            );
+        } else {
+            eprintln!("{printer}");
+        }
+
+        exit(1);
+    }
+}
+
+fn doctor(args: &DoctorArgs) -> i32 {
+    let mut doctor = Doctor::new().with_crate(&args.path);
+
+    if let Some(toolchain) = &args.toolchain {
+        doctor = doctor.with_toolchain(toolchain.as_str());
+    }
+
+    let report = doctor.run();
+
+    match args.output.message_format {
+        OutputFormat::Human => {
+            colored::control::set_override(!args.output.no_color);
+            println!("{report}");
+            colored::control::unset_override();
+        }
+
+        OutputFormat::Json => {
+            let checks = report
+                .checks()
+                .iter()
+                .map(|check| match check.status() {
+                    CheckStatus::Passed(details) => serde_json::json!({
+                        "check": check.kind().to_string(),
+                        "passed": true,
+                        "details": details,
+                    }),
+
+                    CheckStatus::Failed { reason, hint } => serde_json::json!({
+                        "check": check.kind().to_string(),
+                        "passed": false,
+                        "reason": reason,
+                        "hint": hint,
+                    }),
+                })
+                .collect::<Vec<_>>();
+
+            println!(
+                "{}",
+                serde_json::json!({
+                    "reason": "doctor",
+                    "healthy": report.is_healthy(),
+                    "checks": checks,
+                })
+            );
+        }
+    }
+
+    i32::from(!report.is_healthy())
+}
+
+fn run(artifact: Artifact, args: &BuildArgs) -> Result<()> {
+    let mut builder = Builder::new(&args.path)?;
+
+    if args.release {
+        builder = builder.set_profile(Profile::Release);
+    }
+
+    match args.profile {
+        Some(ProfileArg::Debug) => builder = builder.set_profile(Profile::Debug),
+        Some(ProfileArg::Release) => builder = builder.set_profile(Profile::Release),
+        None => {}
+    }
+
+    match args.crate_type {
+        Some(CrateTypeArg::Lib) => builder = builder.set_crate_type(CrateType::Library),
+        Some(CrateTypeArg::Bin) => builder = builder.set_crate_type(CrateType::Binary),
+        None => {}
+    }
+
+    if let Some(arch) = &args.arch {
+        builder = builder.set_target_cpu(arch);
+    }
+
+    if let Some(features) = &args.features {
+        builder = builder.set_features(features);
+    }
+
+    if let Some(toolchain) = &args.toolchain {
+        builder = builder.with_toolchain(toolchain.as_str());
+    }
+
+    // without a build script there is no `OUT_DIR` to fall back to
+    builder = match &args.target_dir {
+        Some(target_dir) => builder.with_target_dir(target_dir),
+        None => builder.with_target_dir(args.path.join("target").join("ptx-builder")),
+    };
+
+    let output_args = &args.output;
+
+    if output_args.no_color || output_args.message_format == OutputFormat::Json {
+        builder = builder.disable_colors();
+    }
+
+    // stdout is reserved for the result, so the cargo output goes to stderr
+    let status = builder.build_live(|line| eprintln!("{line}"), |line| eprintln!("{line}"))?;
+
+    let BuildStatus::Success(output) = status else {
+        if output_args.message_format == OutputFormat::Json {
+            println!("{}", serde_json::json!({ "reason": "not-needed" }));
+        }
+
+        return Ok(());
+    };
+
+    match (artifact, output_args.message_format) {
+        (Artifact::Assembly, OutputFormat::Human) => {
+            println!("{}", output.get_assembly_path().display());
+        }
+
+        (Artifact::Assembly, OutputFormat::Json) => println!(
+            "{}",
+            serde_json::json!({
+                "reason": "assembly",
+                "path": output.get_assembly_path(),
+            })
+        ),
+
+        (Artifact::Dependencies, OutputFormat::Human) => {
+            for path in output.dependencies()? {
+                println!("{}", path.display());
+            }
+        }
+
+        (Artifact::Dependencies, OutputFormat::Json) => println!(
+            "{}",
+            serde_json::json!({
+                "reason": "dependencies",
+                "paths": output.dependencies()?,
+            })
+        ),
+    }
+
+    Ok(())
+}
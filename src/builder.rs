@@ -1,24 +1,47 @@
 use std::{
-    collections::HashMap,
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
     env,
     ffi::OsString,
     fmt,
-    fs::{read_to_string, write, File},
+    fs::{metadata, read, read_to_string, write, File},
     io::{BufReader, Read},
     path::{Path, PathBuf},
     sync::LazyLock,
+    time::{Duration, Instant, SystemTime},
+};
+
+#[cfg(feature = "async")]
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
 };
 
 use regex::Regex;
 
+#[cfg(feature = "async")]
+use crate::executable::runner::unblock;
+#[cfg(feature = "compression")]
+use crate::reporter::CompressionReport;
 use crate::{
+    doctor,
+    environment::EnvPolicy,
     error::{BuildErrorKind, Error, Result, ResultExt},
-    executable::{Cargo, ExecutableRunner},
+    event::BuildEvent,
+    executable::{CancellationToken, CargoWith, ExecutableRunner, Output, Ptxas, Rustc, Toolchain},
+    fingerprint::Fingerprint,
+    fresh::FreshnessRecord,
+    lock::BuildLock,
+    postprocess::PostProcess,
+    reporter::{BuildReport, CrateTiming, KernelUsage},
     source::Crate,
 };
 
 const LAST_BUILD_CMD: &str = ".last-build-command";
-const TARGET_NAME: &str = "nvptx64-nvidia-cuda";
+const FRESHNESS_RECORD: &str = ".fresh";
+pub(crate) const TARGET_NAME: &str = "nvptx64-nvidia-cuda";
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(600);
 
 /// Core of the crate - PTX assembly build controller.
 #[derive(Debug)]
@@ -28,10 +51,26 @@ pub struct Builder {
     profile: Profile,
     colors: bool,
     crate_type: Option<CrateType>,
+    target: Target,
     message_format: MessageFormat,
     prefix: String,
+    features: Vec<String>,
+    target_cpu: Option<String>,
+    build_std: Vec<String>,
+    build_std_features: Vec<String>,
+    expected_kernels: Vec<String>,
+    ptxas: Option<(Ptxas, String)>,
+    post_processes: Vec<Box<dyn PostProcess>>,
+    toolchain: Toolchain,
+    target_dir: Option<PathBuf>,
+    lock_timeout: Duration,
+    timeout: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+    fast_path: bool,
+    crate_timings: bool,
 
     env: HashMap<OsString, OsString>,
+    env_policy: EnvPolicy,
 }
 
 /// Successful build output.
@@ -40,6 +79,8 @@ pub struct BuildOutput<'a> {
     builder: &'a Builder,
     output_path: PathBuf,
     file_suffix: String,
+    cargo_duration: Option<Duration>,
+    crate_timings: Vec<CrateTiming>,
 }
 
 /// Non-failed build status.
@@ -55,6 +96,27 @@ pub enum BuildStatus<'a> {
     NotNeeded,
 }
 
+/// Line of the `cargo` output of an [`AsyncBuild`].
+#[cfg(feature = "async")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OutputLine {
+    Stdout(String),
+    Stderr(String),
+}
+
+/// Running build started by [`Builder::build_async`].
+///
+/// Polling the stream drives the build. Dropping it before completion kills
+/// `cargo`.
+#[cfg(feature = "async")]
+#[must_use = "the build only runs while it is polled"]
+pub struct AsyncBuild<'a> {
+    #[allow(clippy::type_complexity)]
+    build: Option<Pin<Box<dyn Future<Output = Result<BuildStatus<'a>>> + Send + 'a>>>,
+    lines: tokio::sync::mpsc::UnboundedReceiver<OutputLine>,
+    status: Option<Result<BuildStatus<'a>>>,
+}
+
 /// Debug / Release profile.
 ///
 /// # Usage
@@ -69,7 +131,7 @@ pub enum BuildStatus<'a> {
 /// # Ok(())
 /// # }
 /// ```
-#[derive(PartialEq, Eq, Clone, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum Profile {
     /// Equivalent for `cargo-build` **without** `--release` flag.
     Debug,
@@ -136,12 +198,242 @@ pub enum MessageFormat {
 /// # Ok(())
 /// # }
 /// ```
-#[derive(Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum CrateType {
     Library,
     Binary,
 }
 
+/// Device target, which the crate is built for.
+///
+/// A path to a target specification JSON, e.g. with a tweaked data layout,
+/// has no prebuilt `core`, so it usually has to be combined with
+/// [`Builder::build_std`].
+///
+/// # Usage
+/// ``` no_run
+/// use ptx_builder::prelude::*;
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// Builder::new(".")?
+///     .with_target("targets/nvptx64-custom.json")
+///     .build_std(["core"])
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub enum Target {
+    /// Built-in target triple, `nvptx64-nvidia-cuda` by default.
+    Builtin(String),
+
+    /// Path to a target specification JSON.
+    Spec(PathBuf),
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target::from(GpuTarget::default())
+    }
+}
+
+impl From<GpuTarget> for Target {
+    fn from(gpu: GpuTarget) -> Self {
+        Target::Builtin(String::from(gpu.get_triple()))
+    }
+}
+
+impl From<&str> for Target {
+    /// Like `cargo --target`, names with a `.json` extension are treated as
+    /// paths to a target specification.
+    fn from(name: &str) -> Self {
+        if Path::new(name).extension().is_some_and(|ext| ext == "json") {
+            Target::Spec(PathBuf::from(name))
+        } else {
+            Target::Builtin(String::from(name))
+        }
+    }
+}
+
+impl From<String> for Target {
+    fn from(name: String) -> Self {
+        Target::from(name.as_str())
+    }
+}
+
+impl From<PathBuf> for Target {
+    fn from(path: PathBuf) -> Self {
+        Target::Spec(path)
+    }
+}
+
+impl From<&Path> for Target {
+    fn from(path: &Path) -> Self {
+        Target::Spec(path.to_path_buf())
+    }
+}
+
+impl Target {
+    /// Returns the name of the target, which `cargo` uses for the artifact
+    /// directory and `[target.<name>]` configs.
+    ///
+    /// For a target specification, it is the file stem of the JSON.
+    #[must_use]
+    pub fn get_name(&self) -> String {
+        match self {
+            Target::Builtin(name) => name.clone(),
+            Target::Spec(path) => path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// Returns the GPU architecture of the target.
+    ///
+    /// For a target specification, it is decided by the `arch` field.
+    #[must_use]
+    pub fn get_gpu(&self) -> GpuTarget {
+        let is_amdgpu = match self {
+            Target::Builtin(name) => name.starts_with("amdgcn-"),
+            Target::Spec(_) => read_to_string(self.get_cargo_arg())
+                .ok()
+                .and_then(|spec| serde_json::from_str::<serde_json::Value>(&spec).ok())
+                .is_some_and(|spec| spec["arch"] == "amdgpu"),
+        };
+
+        if is_amdgpu {
+            GpuTarget::Amdgpu
+        } else {
+            GpuTarget::Nvptx
+        }
+    }
+
+    /// Returns the argument of `--target`, with the path of a target
+    /// specification made absolute, as `cargo` runs in the crate directory.
+    pub(crate) fn get_cargo_arg(&self) -> String {
+        match self {
+            Target::Builtin(name) => name.clone(),
+            Target::Spec(path) => env::current_dir()
+                .map_or_else(|_| path.clone(), |cwd| cwd.join(path))
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+}
+
+/// GPU architecture, which decides how the device crate is built and what
+/// kind of artifact it produces.
+///
+/// # Usage
+/// ``` no_run
+/// use ptx_builder::prelude::*;
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// Builder::new(".")?
+///     .with_target(GpuTarget::Amdgpu)
+///     .set_target_cpu("gfx90a")
+///     .build_std(["core"])
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default)]
+pub enum GpuTarget {
+    /// NVIDIA GPUs, which run PTX assembly (default).
+    #[default]
+    Nvptx,
+
+    /// AMD GPUs, which run ELF code objects.
+    Amdgpu,
+}
+
+impl GpuTarget {
+    /// Returns the built-in target triple.
+    #[must_use]
+    pub fn get_triple(self) -> &'static str {
+        match self {
+            GpuTarget::Nvptx => TARGET_NAME,
+            GpuTarget::Amdgpu => "amdgcn-amd-amdhsa",
+        }
+    }
+
+    /// Returns the file extension of the device artifact.
+    #[must_use]
+    pub fn get_artifact_extension(self) -> &'static str {
+        match self {
+            GpuTarget::Nvptx => "ptx",
+            GpuTarget::Amdgpu => "elf",
+        }
+    }
+
+    /// Returns the `--crate-type` to build library crates with.
+    ///
+    /// The PTX assembly is linked from an `rlib` by `llvm-bitcode-linker`,
+    /// while the code object is linked directly by `rust-lld`.
+    #[must_use]
+    pub fn get_library_crate_type(self) -> &'static str {
+        match self {
+            GpuTarget::Nvptx => "cdylib,rlib",
+            GpuTarget::Amdgpu => "cdylib",
+        }
+    }
+
+    /// Returns whether a target CPU has to be set, as the code objects are
+    /// specific to a GPU generation.
+    #[must_use]
+    pub fn requires_target_cpu(self) -> bool {
+        self == GpuTarget::Amdgpu
+    }
+
+    /// Returns bool indicating whether `target_cpu` is named like the CPUs of
+    /// this architecture, e.g. `sm_75` or `gfx90a`.
+    #[must_use]
+    pub fn is_valid_target_cpu(self, target_cpu: &str) -> bool {
+        match self {
+            GpuTarget::Nvptx => target_cpu.starts_with("sm_"),
+            GpuTarget::Amdgpu => target_cpu.starts_with("gfx"),
+        }
+    }
+
+    /// Returns an example of a valid target CPU.
+    #[must_use]
+    pub fn get_example_target_cpu(self) -> &'static str {
+        match self {
+            GpuTarget::Nvptx => "sm_75",
+            GpuTarget::Amdgpu => "gfx90a",
+        }
+    }
+
+    /// Returns whether rustup ships a prebuilt `core` for the target.
+    pub(crate) fn has_prebuilt_core(self) -> bool {
+        self == GpuTarget::Nvptx
+    }
+
+    /// Returns the names of the kernels in the device `artifact`.
+    ///
+    /// PTX declares them with `.entry`, while every kernel in a code object
+    /// has a `<name>.kd` kernel descriptor symbol.
+    pub(crate) fn get_kernels(self, artifact: &[u8]) -> BTreeSet<String> {
+        match self {
+            GpuTarget::Nvptx => String::from_utf8_lossy(artifact)
+                .lines()
+                .filter_map(|line| line.split_once(".entry ")?.1.split('(').next())
+                .map(|name| String::from(name.trim()))
+                .collect(),
+
+            GpuTarget::Amdgpu => artifact
+                .split(|byte| *byte == 0)
+                .filter_map(|symbol| symbol.strip_suffix(b".kd"))
+                .map(|name| String::from_utf8_lossy(name).into_owned())
+                .collect(),
+        }
+    }
+}
+
 impl Builder {
     /// Construct a builder for device crate at `path`.
     ///
@@ -163,17 +455,52 @@ impl Builder {
     /// # Ok(())
     /// # }
     /// ```
+    ///
+    /// The defaults can be configured declaratively in the device crate's
+    /// `Cargo.toml`, and are overridden by explicit calls on the builder:
+    /// ```toml
+    /// [package.metadata.ptx-builder]
+    /// profile = "release"        # or "debug"
+    /// crate-type = "lib"         # or "bin"
+    /// features = ["fast-math"]
+    /// target-cpu = "sm_75"
+    /// kernels = ["the_kernel"]   # entry points that must be emitted
+    /// env = { SOME_VAR = "1" }
+    /// ```
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let source_crate = Crate::analyse(path).context("Unable to analyse source crate")?;
+        let metadata = source_crate.get_metadata().clone();
+
         Ok(Builder {
-            source_crate: Crate::analyse(path).context("Unable to analyse source crate")?,
+            source_crate,
             // TODO: choose automatically, e.g.:
             // `env::var("PROFILE").unwrap_or("release".to_string())`
-            profile: Profile::Release,
+            profile: metadata.profile.unwrap_or(Profile::Release),
             colors: true,
-            crate_type: None,
+            crate_type: metadata.crate_type,
+            target: Target::default(),
             message_format: MessageFormat::Human,
             prefix: String::new(),
-            env: HashMap::new(),
+            features: metadata.features.unwrap_or_default(),
+            target_cpu: metadata.target_cpu,
+            build_std: Vec::new(),
+            build_std_features: Vec::new(),
+            expected_kernels: metadata.kernels.unwrap_or_default(),
+            ptxas: None,
+            post_processes: Vec::new(),
+            toolchain: Toolchain::Ambient,
+            target_dir: None,
+            lock_timeout: DEFAULT_LOCK_TIMEOUT,
+            timeout: None,
+            cancellation: None,
+            fast_path: true,
+            crate_timings: false,
+            env: metadata
+                .env
+                .into_iter()
+                .map(|(key, val)| (key.into(), val.into()))
+                .collect(),
+            env_policy: EnvPolicy::new(),
         })
     }
 
@@ -185,7 +512,7 @@ impl Builder {
     pub fn is_build_needed() -> bool {
         let recursive_env = env::var("PTX_CRATE_BUILDING");
 
-        let is_recursive_build = recursive_env.map_or(false, |recursive_env| recursive_env == "1");
+        let is_recursive_build = recursive_env.is_ok_and(|recursive_env| recursive_env == "1");
 
         !is_recursive_build
     }
@@ -196,6 +523,10 @@ impl Builder {
         self.source_crate.get_name()
     }
 
+    pub(crate) fn get_crate_path(&self) -> &Path {
+        self.source_crate.get_path()
+    }
+
     /// Disable colors for internal calls to `cargo`.
     #[must_use]
     pub fn disable_colors(mut self) -> Self {
@@ -224,62 +555,688 @@ impl Builder {
         self
     }
 
-    /// Set the message format.
-    #[must_use]
-    pub fn set_message_format(mut self, message_format: MessageFormat) -> Self {
-        self.message_format = message_format;
-        self
+    /// Set the message format.
+    #[must_use]
+    pub fn set_message_format(mut self, message_format: MessageFormat) -> Self {
+        self.message_format = message_format;
+        self
+    }
+
+    /// Set the build command prefix.
+    #[must_use]
+    pub fn set_prefix(mut self, prefix: String) -> Self {
+        self.prefix = prefix;
+        self
+    }
+
+    /// Set the crate features to enable, replacing any features from the
+    /// crate metadata.
+    #[must_use]
+    pub fn set_features<I: IntoIterator<Item = S>, S: Into<String>>(mut self, features: I) -> Self {
+        self.features = features.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Set the target to build for, defaults to `nvptx64-nvidia-cuda`.
+    ///
+    /// Can also be a [`GpuTarget`], e.g. `GpuTarget::Amdgpu` for the
+    /// `amdgcn-amd-amdhsa` target.
+    #[must_use]
+    pub fn with_target<T: Into<Target>>(mut self, target: T) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    /// Set the target CPU (e.g. `sm_75` or `gfx90a`) to generate the device
+    /// artifact for.
+    #[must_use]
+    pub fn set_target_cpu<S: Into<String>>(mut self, target_cpu: S) -> Self {
+        self.target_cpu = Some(target_cpu.into());
+        self
+    }
+
+    /// Build the standard library crates (e.g. `core` and `alloc`) from source
+    /// with `-Z build-std`, instead of using the prebuilt ones of the target.
+    ///
+    /// This requires the `rust-src` component, but not the prebuilt target,
+    /// and builds the crates with the same target CPU as the device crate.
+    #[must_use]
+    pub fn build_std<I: IntoIterator<Item = S>, S: AsRef<str>>(mut self, crates: I) -> Self {
+        self.build_std = crates
+            .into_iter()
+            .map(|name| String::from(name.as_ref()))
+            .collect();
+        self
+    }
+
+    /// Set the features of the standard library crates, which are built with
+    /// [`Builder::build_std`], e.g. `compiler-builtins-mem`.
+    #[must_use]
+    pub fn build_std_features<I: IntoIterator<Item = S>, S: AsRef<str>>(
+        mut self,
+        features: I,
+    ) -> Self {
+        self.build_std_features = features
+            .into_iter()
+            .map(|name| String::from(name.as_ref()))
+            .collect();
+        self
+    }
+
+    /// Set the kernel entry points which must be present in the PTX assembly,
+    /// replacing any kernels from the crate metadata.
+    #[must_use]
+    pub fn set_expected_kernels<I: IntoIterator<Item = S>, S: Into<String>>(
+        mut self,
+        kernels: I,
+    ) -> Self {
+        self.expected_kernels = kernels.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Compile the PTX assembly into a cubin for the GPU architecture `arch`
+    /// (e.g. `sm_75`) with `ptxas` after every build.
+    ///
+    /// The cubin and the register and memory usage of the kernels are
+    /// available through [`BuildOutput::get_cubin_path`] and
+    /// [`BuildOutput::get_kernel_usage`]. This has no effect on AMDGPU
+    /// targets, whose artifacts are code objects already.
+    ///
+    /// # Usage
+    /// ```no_run
+    /// use ptx_builder::prelude::*;
+    /// # use ptx_builder::error::Result;
+    ///
+    /// # fn main() -> Result<()> {
+    /// let builder = Builder::new(".")?.with_ptxas(Ptxas::default(), "sm_75");
+    ///
+    /// if let BuildStatus::Success(output) = builder.build()? {
+    ///     for kernel in output.get_kernel_usage()? {
+    ///         eprintln!("{}: {} registers", kernel.name, kernel.registers);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[must_use]
+    pub fn with_ptxas<S: Into<String>>(mut self, ptxas: Ptxas, arch: S) -> Self {
+        self.ptxas = Some((ptxas, arch.into()));
+        self
+    }
+
+    /// Append a rewriting step of the PTX assembly, e.g.
+    /// [`StripComments`](crate::postprocess::StripComments).
+    ///
+    /// The steps run in order after every build, and their result is written
+    /// next to the original assembly. [`BuildOutput::get_assembly_path`] then
+    /// returns the processed file, which is also the input of `ptxas`. This
+    /// has no effect on AMDGPU targets, whose artifacts are code objects.
+    #[must_use]
+    pub fn with_post_process<P: PostProcess + 'static>(mut self, step: P) -> Self {
+        self.post_processes.push(Box::new(step));
+        self
+    }
+
+    /// Set the Rust toolchain to build the device crate with, e.g.
+    /// `"nightly-2024-05-01"` for the rustup toolchain of that name.
+    ///
+    /// By default, the `cargo` in `PATH` is used, which inside a build script
+    /// is usually the toolchain of the host crate.
+    #[must_use]
+    pub fn with_toolchain<T: Into<Toolchain>>(mut self, toolchain: T) -> Self {
+        self.toolchain = toolchain.into();
+        self
+    }
+
+    /// Set the directory which the device crate is built in.
+    ///
+    /// By default, the `OUT_DIR` of the calling build script is used, so that
+    /// the artifacts are cleaned together with the host crate. Outside of a
+    /// build script, the builder falls back to its own `OUT_DIR`.
+    #[must_use]
+    pub fn with_target_dir<P: AsRef<Path>>(mut self, target_dir: P) -> Self {
+        self.target_dir = Some(target_dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// Set how long to wait for other processes which build the same crate
+    /// into the same directory, defaults to 10 minutes.
+    ///
+    /// When it runs out, the build fails with [`BuildErrorKind::LockTimeout`].
+    #[must_use]
+    pub fn with_lock_timeout(mut self, lock_timeout: Duration) -> Self {
+        self.lock_timeout = lock_timeout;
+        self
+    }
+
+    /// Abort the `cargo` invocation if it runs longer than `timeout`.
+    ///
+    /// When it runs out, the build fails with [`BuildErrorKind::TimedOut`].
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Abort the `cargo` invocation once the `token` is cancelled.
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Always invoke `cargo`, even if the last build is still up to date.
+    ///
+    /// By default, the modification times and sizes of the files reported by
+    /// [`BuildOutput::dependencies`] are recorded after every build, and
+    /// `cargo` is skipped entirely while none of them have changed.
+    #[must_use]
+    pub fn disable_fast_path(mut self) -> Self {
+        self.fast_path = false;
+        self
+    }
+
+    /// Record the compile time of every crate for [`BuildOutput::report`].
+    ///
+    /// The times require a nightly `cargo`, as they are reported with the
+    /// unstable `--timings=json`. They are only part of the JSON messages, so
+    /// with [`MessageFormat::Human`] or [`MessageFormat::Short`], `cargo` is
+    /// invoked with `--message-format=json,json-render-diagnostics`, which
+    /// still prints the diagnostics to stderr, and the JSON messages are not
+    /// passed on to `on_stdout_line`.
+    #[must_use]
+    pub fn enable_crate_timings(mut self) -> Self {
+        self.crate_timings = true;
+        self
+    }
+
+    /// Inserts or updates an environment variable for the build process.
+    #[must_use]
+    pub fn with_env<K: Into<OsString>, V: Into<OsString>>(mut self, key: K, val: V) -> Self {
+        self.env.insert(key.into(), val.into());
+        self
+    }
+
+    /// Set the policy for which environment variables of the current process
+    /// are inherited by the build process.
+    ///
+    /// By default, the variables that `cargo` sets for the host crate's build
+    /// script (e.g. `TARGET`, `RUSTC_WRAPPER`, `CARGO_ENCODED_RUSTFLAGS` or
+    /// `CARGO_CFG_*`) are stripped, see
+    /// [`EnvPolicy`](crate::environment::EnvPolicy).
+    #[must_use]
+    pub fn with_env_policy(mut self, env_policy: EnvPolicy) -> Self {
+        self.env_policy = env_policy;
+        self
+    }
+
+    /// Performs an actual build: runs `cargo` with proper flags and
+    /// environment.
+    pub fn build(&self) -> Result<BuildStatus<'_>> {
+        self.build_live(|_line| (), |_line| ())
+    }
+
+    /// Performs an actual build: runs `cargo` with proper flags and
+    /// environment.
+    ///
+    /// Before `cargo` is invoked, the toolchain is checked for the
+    /// `nvptx64-nvidia-cuda` target and the `llvm-bitcode-linker` component,
+    /// failing fast with [`BuildErrorKind::TargetNotInstalled`] or
+    /// [`BuildErrorKind::ComponentNotInstalled`] instead of a wall of compiler
+    /// errors.
+    pub fn build_live<O: FnMut(&str), E: FnMut(&str)>(
+        &self,
+        on_stdout_line: O,
+        mut on_stderr_line: E,
+    ) -> Result<BuildStatus<'_>> {
+        self.run_build(&self.message_format, on_stdout_line, |line| {
+            if Self::output_is_not_verbose(line) {
+                on_stderr_line(line);
+            }
+        })
+    }
+
+    /// Performs an actual build like [`Builder::build_live`], but reports
+    /// typed [`BuildEvent`]s instead of raw output lines.
+    ///
+    /// `cargo` is always invoked with `--message-format=json`, so the
+    /// configured [`MessageFormat`] only decides whether the rendered
+    /// diagnostics are short. When the build is up to date and `cargo` is
+    /// skipped, only [`BuildEvent::Finished`] is reported.
+    ///
+    /// # Usage
+    /// ``` no_run
+    /// use ptx_builder::prelude::*;
+    /// # use ptx_builder::error::Result;
+    ///
+    /// # fn main() -> Result<()> {
+    /// Builder::new(".")?.build_with_events(|event| match event {
+    ///     BuildEvent::Compiling { package, version } => println!("{package} v{version}"),
+    ///     BuildEvent::Diagnostic {
+    ///         rendered: Some(rendered),
+    ///         ..
+    ///     } => eprint!("{rendered}"),
+    ///     _ => {}
+    /// })?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn build_with_events<F: FnMut(BuildEvent)>(&self, on_event: F) -> Result<BuildStatus<'_>> {
+        let started = Instant::now();
+        let on_event = RefCell::new(on_event);
+
+        // diagnostics are only part of the JSON messages, but the build error
+        // should still show them
+        let mut rendered_errors = Vec::new();
+
+        let message_format = MessageFormat::Json {
+            render_diagnostics: false,
+            short: matches!(
+                self.message_format,
+                MessageFormat::Short | MessageFormat::Json { short: true, .. }
+            ),
+            ansi: self.colors,
+        };
+
+        let status = self
+            .run_build(
+                &message_format,
+                |line| {
+                    for event in BuildEvent::from_json_message(line) {
+                        if let BuildEvent::Diagnostic {
+                            level,
+                            rendered: Some(rendered),
+                            ..
+                        } = &event
+                        {
+                            if level == "error" {
+                                rendered_errors.extend(rendered.lines().map(String::from));
+                            }
+                        }
+
+                        (on_event.borrow_mut())(event);
+                    }
+                },
+                |line| {
+                    if let Some(event) = BuildEvent::from_status_line(line) {
+                        (on_event.borrow_mut())(event);
+                    }
+                },
+            )
+            .map_err(|error| match error.kind() {
+                BuildErrorKind::BuildFailed(lines) if !rendered_errors.is_empty() => {
+                    Error::from(BuildErrorKind::BuildFailed(
+                        rendered_errors.iter().chain(lines).cloned().collect(),
+                    ))
+                }
+
+                _ => error,
+            })?;
+
+        if let BuildStatus::Success(_) = status {
+            (on_event.into_inner())(BuildEvent::Finished {
+                duration: started.elapsed(),
+            });
+        }
+
+        Ok(status)
+    }
+
+    /// Runs the build with raw `cargo` output callbacks.
+    fn run_build<O: FnMut(&str), E: FnMut(&str)>(
+        &self,
+        message_format: &MessageFormat,
+        mut on_stdout_line: O,
+        on_stderr_line: E,
+    ) -> Result<BuildStatus<'_>> {
+        if !Self::is_build_needed() {
+            return Ok(BuildStatus::NotNeeded);
+        }
+
+        if let Some(output) = self.load_fresh_output()? {
+            self.post_process(&output)?;
+            self.compile_cubin(&output)?;
+
+            return Ok(BuildStatus::Success(output));
+        }
+
+        self.check_target_cpu()?;
+
+        let rustc_version = self.get_rustc_version().run()?.stdout;
+        let target_libdir = self.check_toolchain(&rustc_version)?;
+
+        let crate_type = self
+            .source_crate
+            .get_crate_type(self.crate_type, self.target.get_gpu())?;
+        let output_path = self.get_output_path(&rustc_version)?;
+
+        // concurrent builds share the cached build command until it is read
+        let _lock = BuildLock::acquire(&output_path, self.lock_timeout)?;
+        let build_started = SystemTime::now();
+        let cargo_started = Instant::now();
+
+        let cargo_format = self.get_cargo_message_format(message_format);
+        let forward_stdout = cargo_format == *message_format;
+
+        let cargo_output = self
+            .get_cargo(crate_type, &cargo_format, &output_path)
+            .run_live(
+                |line| {
+                    if forward_stdout {
+                        on_stdout_line(line);
+                    }
+                },
+                on_stderr_line,
+            )
+            .map_err(Self::map_cargo_error)?;
+
+        let output = self
+            .prepare_output(output_path, &cargo_output.stderr, crate_type)?
+            .with_timings(cargo_started.elapsed(), &cargo_output.stdout);
+        self.store_fresh_output(&output, &target_libdir, build_started)?;
+        self.post_process(&output)?;
+        self.compile_cubin(&output)?;
+
+        Ok(BuildStatus::Success(output))
+    }
+
+    /// Performs an actual build like [`Builder::build_live`], but without
+    /// blocking the async runtime.
+    ///
+    /// The returned [`AsyncBuild`] is a `Stream` of the `cargo` output lines,
+    /// and resolves to the [`BuildStatus`] with [`AsyncBuild::status`].
+    /// Note that the toolchain checks before the build still briefly block.
+    ///
+    /// # Usage
+    /// ``` no_run
+    /// use ptx_builder::prelude::*;
+    /// # use ptx_builder::error::Result;
+    ///
+    /// # async fn hot_reload() -> Result<()> {
+    /// let builder = Builder::new(".")?;
+    ///
+    /// match builder.build_async().status().await? {
+    ///     BuildStatus::Success(output) => {
+    ///         // reload `output.get_assembly_path()`
+    ///     }
+    ///
+    ///     BuildStatus::NotNeeded => {}
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "async")]
+    #[must_use = "the build only runs while it is polled"]
+    pub fn build_async(&self) -> AsyncBuild<'_> {
+        let (sender, lines) = tokio::sync::mpsc::unbounded_channel();
+
+        AsyncBuild {
+            build: Some(Box::pin(self.run_async_build(sender))),
+            lines,
+            status: None,
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn run_async_build(
+        &self,
+        lines: tokio::sync::mpsc::UnboundedSender<OutputLine>,
+    ) -> Result<BuildStatus<'_>> {
+        if !Self::is_build_needed() {
+            return Ok(BuildStatus::NotNeeded);
+        }
+
+        if let Some(output) = self.load_fresh_output()? {
+            self.post_process(&output)?;
+            self.compile_cubin_async(&output).await?;
+
+            return Ok(BuildStatus::Success(output));
+        }
+
+        self.check_target_cpu()?;
+
+        let rustc_version = self
+            .get_rustc_version()
+            .run_async(|_line| (), |_line| ())
+            .await?
+            .stdout;
+        let target_libdir = self.check_toolchain_async(&rustc_version).await?;
+
+        let crate_type = self
+            .source_crate
+            .get_crate_type(self.crate_type, self.target.get_gpu())?;
+        let output_path = self.get_output_path(&rustc_version)?;
+
+        // concurrent builds share the cached build command until it is read
+        let _lock = BuildLock::acquire_async(&output_path, self.lock_timeout).await?;
+        let build_started = SystemTime::now();
+        let cargo_started = Instant::now();
+
+        let cargo_format = self.get_cargo_message_format(&self.message_format);
+        let forward_stdout = cargo_format == self.message_format;
+
+        // nobody listens anymore if only the status is awaited
+        let cargo_output = self
+            .get_cargo(crate_type, &cargo_format, &output_path)
+            .run_async(
+                |line| {
+                    if forward_stdout {
+                        let _ = lines.send(OutputLine::Stdout(String::from(line)));
+                    }
+                },
+                |line| {
+                    if Self::output_is_not_verbose(line) {
+                        let _ = lines.send(OutputLine::Stderr(String::from(line)));
+                    }
+                },
+            )
+            .await
+            .map_err(Self::map_cargo_error)?;
+
+        let output = self
+            .prepare_output(output_path, &cargo_output.stderr, crate_type)?
+            .with_timings(cargo_started.elapsed(), &cargo_output.stdout);
+        self.store_fresh_output(&output, &target_libdir, build_started)?;
+        self.post_process(&output)?;
+        self.compile_cubin_async(&output).await?;
+
+        Ok(BuildStatus::Success(output))
+    }
+
+    /// Runs the steps of [`Builder::with_post_process`] on the PTX assembly.
+    ///
+    /// An unchanged processed file is not rewritten, so that its cubin stays
+    /// up to date.
+    fn post_process(&self, output: &BuildOutput) -> Result<()> {
+        if !self.is_post_processed() {
+            return Ok(());
+        }
+
+        let mut assembly = read_to_string(output.get_original_assembly_path())
+            .context(BuildErrorKind::OtherError)?;
+
+        for step in &self.post_processes {
+            assembly = step.process(&assembly)?;
+        }
+
+        let processed_path = output.get_assembly_path();
+
+        if read_to_string(&processed_path).is_ok_and(|existing| existing == assembly) {
+            return Ok(());
+        }
+
+        write(processed_path, assembly).context(BuildErrorKind::OtherError)?;
+
+        Ok(())
+    }
+
+    fn is_post_processed(&self) -> bool {
+        !self.post_processes.is_empty() && self.target.get_gpu() == GpuTarget::Nvptx
+    }
+
+    /// Compiles the PTX assembly into a cubin, if enabled with
+    /// [`Builder::with_ptxas`].
+    fn compile_cubin(&self, output: &BuildOutput) -> Result<()> {
+        if let Some(mut ptxas) = self.get_ptxas(output) {
+            Self::store_ptxas_log(output, &ptxas.run()?)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "async")]
+    async fn compile_cubin_async(&self, output: &BuildOutput<'_>) -> Result<()> {
+        if let Some(ptxas) = self.get_ptxas(output) {
+            let ptxas_output = ptxas.run_async(|_line| (), |_line| ()).await?;
+            Self::store_ptxas_log(output, &ptxas_output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the `ptxas` invocation, unless the cubin is still newer than
+    /// the PTX assembly.
+    fn get_ptxas(&self, output: &BuildOutput) -> Option<ExecutableRunner<Ptxas>> {
+        let (ptxas, arch) = self.ptxas.as_ref()?;
+        let cubin_path = output.get_cubin_path()?;
+        let assembly_path = output.get_assembly_path();
+
+        let modified = |path: &Path| metadata(path).and_then(|metadata| metadata.modified()).ok();
+
+        // the log is written last, so it is missing if `ptxas` has failed
+        if let (Some(log_modified), Some(assembly_modified)) = (
+            modified(&output.get_ptxas_log_path()?),
+            modified(&assembly_path),
+        ) {
+            if cubin_path.exists() && log_modified >= assembly_modified {
+                return None;
+            }
+        }
+
+        let mut runner = ExecutableRunner::new(ptxas.clone());
+
+        runner
+            .with_args(["-v", "--gpu-name", arch, "--output-file"])
+            .with_args([cubin_path, assembly_path]);
+
+        if let Some(token) = &self.cancellation {
+            runner.with_cancellation(token);
+        }
+
+        Some(runner)
+    }
+
+    /// Stores the `-v` output of `ptxas`, which reports the kernel usage.
+    fn store_ptxas_log(output: &BuildOutput, ptxas_output: &Output) -> Result<()> {
+        let Some(log_path) = output.get_ptxas_log_path() else {
+            return Ok(());
+        };
+
+        write(
+            log_path,
+            format!("{}{}", ptxas_output.stdout, ptxas_output.stderr),
+        )
+        .context(BuildErrorKind::OtherError)?;
+
+        Ok(())
     }
 
-    /// Set the build command prefix.
-    #[must_use]
-    pub fn set_prefix(mut self, prefix: String) -> Self {
-        self.prefix = prefix;
-        self
+    fn get_output_path(&self, rustc_version: &str) -> Result<PathBuf> {
+        self.source_crate
+            .get_output_path(
+                &self.get_target_dir()?,
+                &self.get_fingerprint(rustc_version),
+            )
+            .context("Unable to create output path")
     }
 
-    /// Inserts or updates an environment variable for the build process.
-    #[must_use]
-    pub fn with_env<K: Into<OsString>, V: Into<OsString>>(mut self, key: K, val: V) -> Self {
-        self.env.insert(key.into(), val.into());
-        self
+    /// Returns the configured `cargo rustc` invocation.
+    fn get_cargo(
+        &self,
+        crate_type: &str,
+        message_format: &MessageFormat,
+        output_path: &Path,
+    ) -> ExecutableRunner<CargoWith> {
+        let mut cargo = ExecutableRunner::new(CargoWith::new(self.toolchain.clone()));
+
+        cargo
+            .with_args(self.get_cargo_args(crate_type, message_format))
+            .with_cwd(self.source_crate.get_path())
+            .with_env_policy(&self.env_policy)
+            .with_env("PTX_CRATE_BUILDING", "1")
+            .with_env("CARGO_TARGET_DIR", output_path);
+
+        for (key, val) in &self.env {
+            cargo.with_env(key, val);
+        }
+
+        if let Some(timeout) = self.timeout {
+            cargo.with_timeout(timeout);
+        }
+
+        if let Some(token) = &self.cancellation {
+            cargo.with_cancellation(token);
+        }
+
+        cargo
     }
 
-    /// Performs an actual build: runs `cargo` with proper flags and
-    /// environment.
-    pub fn build(&self) -> Result<BuildStatus> {
-        self.build_live(|_line| (), |_line| ())
+    fn map_cargo_error(error: Error) -> Error {
+        match error.kind() {
+            BuildErrorKind::CommandFailed { stderr, .. } => {
+                #[allow(clippy::manual_filter_map)]
+                let lines = stderr
+                    .trim_matches('\n')
+                    .split('\n')
+                    .filter(|s| Self::output_is_not_verbose(s))
+                    .map(String::from)
+                    .collect();
+
+                Error::from(BuildErrorKind::BuildFailed(lines))
+            }
+            _ => error,
+        }
     }
 
-    /// Performs an actual build: runs `cargo` with proper flags and
-    /// environment.
-    pub fn build_live<O: FnMut(&str), E: FnMut(&str)>(
-        &self,
-        on_stdout_line: O,
-        mut on_stderr_line: E,
-    ) -> Result<BuildStatus> {
-        if !Self::is_build_needed() {
-            return Ok(BuildStatus::NotNeeded);
+    /// Returns the message format, which `cargo` is invoked with, for the
+    /// requested one.
+    ///
+    /// The per-crate compile times are only part of the JSON messages, so
+    /// `cargo` renders the diagnostics itself instead of the human formats.
+    fn get_cargo_message_format(&self, message_format: &MessageFormat) -> MessageFormat {
+        match *message_format {
+            MessageFormat::Human | MessageFormat::Short if self.crate_timings => {
+                MessageFormat::Json {
+                    render_diagnostics: true,
+                    short: *message_format == MessageFormat::Short,
+                    ansi: self.colors,
+                }
+            }
+
+            _ => message_format.clone(),
         }
+    }
 
-        let mut cargo = ExecutableRunner::new(Cargo);
-        let mut args = vec!["rustc"];
+    /// Returns the arguments of the `cargo rustc` invocation.
+    fn get_cargo_args(&self, crate_type: &str, message_format: &MessageFormat) -> Vec<String> {
+        let mut args = vec![String::from("rustc")];
 
         if self.profile == Profile::Release {
-            args.push("--release");
+            args.push(String::from("--release"));
         }
 
-        args.push("--color");
-        args.push(if self.colors { "always" } else { "never" });
+        args.push(String::from("--color"));
+        args.push(String::from(if self.colors { "always" } else { "never" }));
 
-        let mut json_format = String::from("--message-format=json");
-        args.push(match self.message_format {
-            MessageFormat::Human => "--message-format=human",
+        args.push(match *message_format {
+            MessageFormat::Human => String::from("--message-format=human"),
             MessageFormat::Json {
                 render_diagnostics,
                 short,
                 ansi,
             } => {
+                let mut json_format = String::from("--message-format=json");
+
                 if render_diagnostics {
                     json_format.push_str(",json-render-diagnostics");
                 }
@@ -292,78 +1249,365 @@ impl Builder {
                     json_format.push_str(",json-diagnostic-rendered-ansi");
                 }
 
-                &json_format
+                json_format
             }
-            MessageFormat::Short => "--message-format=short",
+            MessageFormat::Short => String::from("--message-format=short"),
         });
 
-        args.push("--target");
-        args.push(TARGET_NAME);
+        // the per-crate compile times are only part of the JSON messages
+        if self.crate_timings && matches!(message_format, MessageFormat::Json { .. }) {
+            args.push(String::from("-Zunstable-options"));
+            args.push(String::from("--timings=json"));
+        }
+
+        args.push(String::from("--target"));
+        args.push(self.target.get_cargo_arg());
+
+        if let Target::Spec(_) = self.target {
+            args.push(String::from("-Zjson-target-spec"));
+        }
+
+        if !self.build_std.is_empty() {
+            args.push(format!("-Zbuild-std={}", self.build_std.join(",")));
+
+            if !self.build_std_features.is_empty() {
+                args.push(format!(
+                    "-Zbuild-std-features={}",
+                    self.build_std_features.join(",")
+                ));
+            }
+
+            // the flags after `--` only apply to the device crate itself
+            if let Some(target_cpu) = &self.target_cpu {
+                args.push(String::from("--config"));
+                args.push(format!(
+                    "target.{}.rustflags=[\"-Ctarget-cpu={target_cpu}\"]",
+                    self.target.get_name()
+                ));
+            }
+        }
+
+        if !self.features.is_empty() {
+            args.push(String::from("--features"));
+            args.push(self.features.join(","));
+        }
 
         match self.crate_type {
             Some(CrateType::Binary) => {
-                args.push("--bin");
-                args.push(self.source_crate.get_name());
+                args.push(String::from("--bin"));
+                args.push(String::from(self.source_crate.get_name()));
             }
 
             Some(CrateType::Library) => {
-                args.push("--lib");
+                args.push(String::from("--lib"));
             }
 
             _ => {}
         }
 
-        args.push("-v");
+        args.push(String::from("-v"));
+        args.push(String::from("--"));
 
-        let crate_type = self.source_crate.get_crate_type(self.crate_type)?;
+        args.push(String::from("--crate-type"));
+        args.push(String::from(crate_type));
 
-        args.push("--");
+        if let Some(target_cpu) = &self.target_cpu {
+            args.push(String::from("-C"));
+            args.push(format!("target-cpu={target_cpu}"));
+        }
 
-        args.push("--crate-type");
-        args.push(crate_type);
+        args
+    }
 
-        let output_path = {
-            self.source_crate
-                .get_output_path()
-                .context("Unable to create output path")?
+    fn get_target_dir(&self) -> Result<PathBuf> {
+        let target_dir = match &self.target_dir {
+            Some(target_dir) => target_dir.clone(),
+            None => {
+                env::var_os("OUT_DIR").map_or_else(|| PathBuf::from(env!("OUT_DIR")), PathBuf::from)
+            }
         };
 
-        cargo
-            .with_args(&args)
-            .with_cwd(self.source_crate.get_path())
-            .with_env("PTX_CRATE_BUILDING", "1")
-            .with_env("CARGO_TARGET_DIR", output_path.clone());
+        // `cargo` runs inside the crate directory, so relative paths would
+        // be resolved against it instead
+        Ok(env::current_dir()
+            .context(BuildErrorKind::OtherError)?
+            .join(target_dir))
+    }
 
-        for (key, val) in &self.env {
-            cargo.with_env(key, val);
+    /// Returns a stable fingerprint of everything that affects the build
+    /// artifacts, so that incompatible builds never share an output directory.
+    fn get_fingerprint(&self, rustc_version: &str) -> String {
+        self.get_settings_fingerprint()
+            .field("rustc", rustc_version)
+            .finish()
+    }
+
+    /// Returns the `rustc -vV` invocation, which identifies the compiler.
+    ///
+    /// It is only run once per build, and its output is shared by the
+    /// toolchain check and the fingerprint.
+    fn get_rustc_version(&self) -> ExecutableRunner<Rustc> {
+        let mut rustc = ExecutableRunner::new(Rustc::new(self.toolchain.clone()));
+        rustc.with_args(["-vV"]);
+        rustc
+    }
+
+    /// Returns a fingerprint of the builder settings, which is available
+    /// without asking the toolchain.
+    fn get_settings_fingerprint(&self) -> Fingerprint {
+        let mut fingerprint = Fingerprint::new();
+
+        fingerprint
+            .field(
+                "crate-path",
+                self.source_crate.get_path().as_os_str().as_encoded_bytes(),
+            )
+            .field("profile", self.profile.to_string())
+            .field("crate-type", format!("{:?}", self.crate_type))
+            .field("target", self.target.get_cargo_arg())
+            .field("target-cpu", self.target_cpu.as_deref().unwrap_or_default());
+
+        let mut features = self.features.iter().collect::<Vec<_>>();
+        features.sort();
+        features.dedup();
+
+        for feature in features {
+            fingerprint.field("feature", feature);
         }
 
-        let cargo_output = cargo
-            .run_live(on_stdout_line, |line| {
-                if Self::output_is_not_verbose(line) {
-                    on_stderr_line(line);
-                }
-            })
-            .map_err(|error| match error.kind() {
-                BuildErrorKind::CommandFailed { stderr, .. } => {
-                    #[allow(clippy::manual_filter_map)]
-                    let lines = stderr
-                        .trim_matches('\n')
-                        .split('\n')
-                        .filter(|s| Self::output_is_not_verbose(s))
-                        .map(String::from)
-                        .collect();
-
-                    Error::from(BuildErrorKind::BuildFailed(lines))
-                }
-                _ => error,
-            })?;
+        for name in &self.build_std {
+            fingerprint.field("build-std", name);
+        }
 
-        Ok(BuildStatus::Success(self.prepare_output(
-            output_path,
-            &cargo_output.stderr,
-            crate_type,
-        )?))
+        for feature in &self.build_std_features {
+            fingerprint.field("build-std-feature", feature);
+        }
+
+        match &self.toolchain {
+            Toolchain::Ambient => fingerprint.field("toolchain", ""),
+            Toolchain::Rustup(name) => fingerprint.field("toolchain", name),
+            Toolchain::Path { cargo, rustc } => fingerprint
+                .field("toolchain-cargo", cargo.as_os_str().as_encoded_bytes())
+                .field("toolchain-rustc", rustc.as_os_str().as_encoded_bytes()),
+        };
+
+        let mut env = self.env.iter().collect::<Vec<_>>();
+        env.sort();
+
+        for (key, val) in env {
+            fingerprint
+                .field("env-key", key.as_encoded_bytes())
+                .field("env-val", val.as_encoded_bytes());
+        }
+
+        for pattern in self.env_policy.allowed() {
+            fingerprint.field("env-allow", pattern);
+        }
+
+        for pattern in self.env_policy.denied() {
+            fingerprint.field("env-deny", pattern);
+        }
+
+        fingerprint
+    }
+
+    /// Returns the location of the record of the last build with the same
+    /// settings, including the variables which select the ambient toolchain.
+    fn get_freshness_record_path(&self) -> Result<PathBuf> {
+        let fingerprint = self
+            .get_settings_fingerprint()
+            .field("prefix", &self.prefix)
+            .field(
+                "path",
+                env::var_os("PATH").unwrap_or_default().as_encoded_bytes(),
+            )
+            .field(
+                "rustup-toolchain",
+                env::var_os("RUSTUP_TOOLCHAIN")
+                    .unwrap_or_default()
+                    .as_encoded_bytes(),
+            )
+            .finish();
+
+        Ok(self
+            .get_target_dir()?
+            .join(self.source_crate.get_output_file_prefix())
+            .join(format!("{FRESHNESS_RECORD}-{fingerprint}")))
+    }
+
+    /// Returns the output of the last build, if none of its inputs have
+    /// changed since.
+    fn load_fresh_output(&self) -> Result<Option<BuildOutput<'_>>> {
+        if !self.fast_path {
+            return Ok(None);
+        }
+
+        let record = match FreshnessRecord::load(&self.get_freshness_record_path()?) {
+            Some(record) if record.is_fresh() => record,
+            _ => return Ok(None),
+        };
+
+        let output = BuildOutput::new(self, record.output_path, record.file_suffix);
+        self.check_expected_kernels(&output)?;
+
+        Ok(Some(output))
+    }
+
+    fn store_fresh_output(
+        &self,
+        output: &BuildOutput,
+        target_libdir: &Path,
+        build_started: SystemTime,
+    ) -> Result<()> {
+        if !self.fast_path {
+            return Ok(());
+        }
+
+        let record_path = self.get_freshness_record_path()?;
+
+        let Ok(mut inputs) = output.dependencies() else {
+            return FreshnessRecord::remove(&record_path);
+        };
+
+        // the toolchain is only checked on the slow path, so its updates
+        // and overrides have to be detected through the files
+        inputs.push(target_libdir.to_path_buf());
+
+        if let Target::Spec(_) = &self.target {
+            inputs.push(PathBuf::from(self.target.get_cargo_arg()));
+        }
+
+        if let Toolchain::Path { cargo, rustc } = &self.toolchain {
+            inputs.push(cargo.clone());
+            inputs.push(rustc.clone());
+        }
+
+        for dir in self.source_crate.get_path().ancestors() {
+            inputs.push(dir.join("rust-toolchain"));
+            inputs.push(dir.join("rust-toolchain.toml"));
+            inputs.push(dir.join(".cargo").join("config"));
+            inputs.push(dir.join(".cargo").join("config.toml"));
+        }
+
+        match FreshnessRecord::new(
+            output.output_path.clone(),
+            output.file_suffix.clone(),
+            inputs,
+            [output.get_original_assembly_path()],
+            build_started,
+        ) {
+            Some(record) => record.store(&record_path),
+            None => FreshnessRecord::remove(&record_path),
+        }
+    }
+
+    /// Returns the library directory of the target in the toolchain, which
+    /// has reported `rustc_version` with `rustc -vV`.
+    fn check_toolchain(&self, rustc_version: &str) -> Result<PathBuf> {
+        Self::find_target_libdir(
+            &self.toolchain,
+            &self.target,
+            !self.build_std.is_empty(),
+            doctor::get_host(rustc_version),
+        )
+    }
+
+    /// Like [`Builder::check_toolchain`], but asks the toolchain on the
+    /// blocking thread pool.
+    #[cfg(feature = "async")]
+    async fn check_toolchain_async(&self, rustc_version: &str) -> Result<PathBuf> {
+        let toolchain = self.toolchain.clone();
+        let target = self.target.clone();
+        let build_std = !self.build_std.is_empty();
+        let host = String::from(doctor::get_host(rustc_version));
+
+        unblock(move || Self::find_target_libdir(&toolchain, &target, build_std, &host)).await
+    }
+
+    fn check_target_cpu(&self) -> Result<()> {
+        let gpu = self.target.get_gpu();
+
+        match &self.target_cpu {
+            Some(target_cpu) if !gpu.is_valid_target_cpu(target_cpu) => {
+                bail!(BuildErrorKind::InvalidTargetCpu {
+                    target: self.target.get_name(),
+                    target_cpu: Some(target_cpu.clone()),
+                    hint: format!(
+                        "Please use a CPU of the target, e.g. `{}`",
+                        gpu.get_example_target_cpu()
+                    ),
+                });
+            }
+
+            None if gpu.requires_target_cpu() => {
+                bail!(BuildErrorKind::InvalidTargetCpu {
+                    target: self.target.get_name(),
+                    target_cpu: None,
+                    hint: format!(
+                        "Please set it with `Builder::set_target_cpu`, e.g. `{}`",
+                        gpu.get_example_target_cpu()
+                    ),
+                });
+            }
+
+            _ => {}
+        }
+
+        if let Some((_, arch)) = &self.ptxas {
+            if gpu == GpuTarget::Nvptx && !gpu.is_valid_target_cpu(arch) {
+                bail!(BuildErrorKind::InvalidTargetCpu {
+                    target: String::from("ptxas"),
+                    target_cpu: Some(arch.clone()),
+                    hint: format!(
+                        "Please use a GPU architecture of `ptxas`, e.g. `{}`",
+                        gpu.get_example_target_cpu()
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the library directory of the target, after checking that
+    /// the toolchain has all the components to build for it.
+    fn find_target_libdir(
+        toolchain: &Toolchain,
+        target: &Target,
+        build_std: bool,
+        host: &str,
+    ) -> Result<PathBuf> {
+        let (target_libdir, has_core) = doctor::find_target_libdir(toolchain, target)?;
+        let is_nvptx = target.get_gpu() == GpuTarget::Nvptx;
+
+        if !build_std && !has_core {
+            bail!(BuildErrorKind::TargetNotInstalled {
+                target: target.get_name(),
+                hint: doctor::target_hint(target),
+            });
+        }
+
+        if !build_std && !is_nvptx {
+            return Ok(target_libdir);
+        }
+
+        let sysroot = doctor::find_sysroot(toolchain)?;
+
+        if build_std && doctor::find_rust_src(&sysroot).is_none() {
+            bail!(BuildErrorKind::ComponentNotInstalled {
+                component: String::from("rust-src"),
+                hint: doctor::rust_src_hint(),
+            });
+        }
+
+        if is_nvptx && doctor::find_bitcode_linker(&sysroot, host).is_none() {
+            bail!(BuildErrorKind::ComponentNotInstalled {
+                component: String::from("llvm-bitcode-linker"),
+                hint: doctor::bitcode_linker_hint(),
+            });
+        }
+
+        Ok(target_libdir)
     }
 
     fn prepare_output(
@@ -371,22 +1615,29 @@ impl Builder {
         output_path: PathBuf,
         cargo_stderr: &str,
         crate_type: &str,
-    ) -> Result<BuildOutput> {
+    ) -> Result<BuildOutput<'_>> {
         static SUFFIX_REGEX: LazyLock<Regex> = LazyLock::new(|| {
             Regex::new(r"-C extra-filename=([\S]+)").expect("Unable to parse regex...")
         });
 
         let crate_name = self.source_crate.get_output_file_prefix();
 
-        // We need the build command to get real output filename.
+        // We need the build command to get real output filename. Among the
+        // commands of the dependencies and the `-Z build-std` crates, only the
+        // device crate has exactly this name and type.
         let build_command = {
             #[allow(clippy::manual_find_map)]
             cargo_stderr
                 .trim_matches('\n')
                 .split('\n')
                 .find(|line| {
-                    line.contains(&format!("--crate-name {crate_name}"))
-                        && line.contains(&format!("--crate-type {crate_type}"))
+                    let args = line.split_whitespace().collect::<Vec<_>>();
+
+                    args.windows(2)
+                        .any(|pair| pair == ["--crate-name", crate_name])
+                        && args
+                            .windows(2)
+                            .any(|pair| pair == ["--crate-type", crate_type])
                 })
                 .map(|line| BuildCommand::Realtime(line.to_string()))
                 .or_else(|| Self::load_cached_build_command(&output_path, &self.prefix))
@@ -408,7 +1659,9 @@ impl Builder {
 
         let output = BuildOutput::new(self, output_path, file_suffix);
 
-        if output.get_assembly_path().exists() {
+        if output.get_original_assembly_path().exists() {
+            self.check_expected_kernels(&output)?;
+
             Ok(output)
         } else if found_suffix {
             Err(BuildErrorKind::InternalError(String::from(
@@ -423,6 +1676,29 @@ impl Builder {
         }
     }
 
+    fn check_expected_kernels(&self, output: &BuildOutput) -> Result<()> {
+        if self.expected_kernels.is_empty() {
+            return Ok(());
+        }
+
+        let artifact =
+            read(output.get_original_assembly_path()).context(BuildErrorKind::OtherError)?;
+        let kernels = self.target.get_gpu().get_kernels(&artifact);
+
+        let missing_kernels = self
+            .expected_kernels
+            .iter()
+            .filter(|kernel| !kernels.contains(*kernel))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if missing_kernels.is_empty() {
+            Ok(())
+        } else {
+            Err(BuildErrorKind::MissingKernels(missing_kernels).into())
+        }
+    }
+
     fn output_is_not_verbose(line: &str) -> bool {
         !line.starts_with("+ ")
             && !line.contains("Running")
@@ -455,9 +1731,22 @@ impl<'a> BuildOutput<'a> {
             builder,
             output_path,
             file_suffix,
+            cargo_duration: None,
+            crate_timings: Vec::new(),
         }
     }
 
+    /// Records how long the `cargo` invocation, which has printed
+    /// `cargo_stdout`, has taken.
+    fn with_timings(mut self, cargo_duration: Duration, cargo_stdout: &str) -> Self {
+        self.cargo_duration = Some(cargo_duration);
+        self.crate_timings = cargo_stdout
+            .lines()
+            .filter_map(CrateTiming::from_json_message)
+            .collect();
+        self
+    }
+
     /// Returns path to PTX assembly file.
     ///
     /// # Usage
@@ -477,19 +1766,121 @@ impl<'a> BuildOutput<'a> {
     /// # Ok(())
     /// # }
     /// ```
+    ///
+    /// With [`Builder::with_post_process`], this is the processed assembly.
     #[must_use]
     pub fn get_assembly_path(&self) -> PathBuf {
+        let original_path = self.get_original_assembly_path();
+
+        if self.builder.is_post_processed() {
+            original_path.with_extension("processed.ptx")
+        } else {
+            original_path
+        }
+    }
+
+    /// Returns path to the artifact, as `rustc` has written it.
+    fn get_original_assembly_path(&self) -> PathBuf {
         self.output_path
-            .join(TARGET_NAME)
+            .join(self.builder.target.get_name())
             .join(self.builder.profile.to_string())
             .join("deps")
             .join(format!(
-                "{}{}.ptx",
+                "{}{}.{}",
                 self.builder.source_crate.get_output_file_prefix(),
                 self.file_suffix,
+                self.builder.target.get_gpu().get_artifact_extension(),
             ))
     }
 
+    /// Returns path to the cubin, which `ptxas` has compiled the PTX assembly
+    /// into, or `None` without [`Builder::with_ptxas`].
+    #[must_use]
+    pub fn get_cubin_path(&self) -> Option<PathBuf> {
+        let (_, arch) = self.builder.ptxas.as_ref()?;
+
+        if self.builder.target.get_gpu() != GpuTarget::Nvptx {
+            return None;
+        }
+
+        Some(
+            self.get_assembly_path()
+                .with_extension(format!("{arch}.cubin")),
+        )
+    }
+
+    /// Returns the register and memory usage of the kernels in the cubin, or
+    /// an empty list without [`Builder::with_ptxas`].
+    pub fn get_kernel_usage(&self) -> Result<Vec<KernelUsage>> {
+        let Some(log_path) = self.get_ptxas_log_path() else {
+            return Ok(Vec::new());
+        };
+
+        let log = read_to_string(log_path).context(BuildErrorKind::OtherError)?;
+
+        Ok(KernelUsage::from_ptxas_output(&log))
+    }
+
+    /// Returns the GPU architecture of the cubin, see
+    /// [`BuildOutput::get_cubin_path`].
+    pub(crate) fn get_cubin_arch(&self) -> Option<&str> {
+        self.get_cubin_path()?;
+        self.builder.ptxas.as_ref().map(|(_, arch)| arch.as_str())
+    }
+
+    fn get_ptxas_log_path(&self) -> Option<PathBuf> {
+        Some(self.get_cubin_path()?.with_extension("cubin.log"))
+    }
+
+    /// Returns the timings of the build and the size of the PTX assembly.
+    ///
+    /// The per-crate compile times are only available if enabled with
+    /// [`Builder::enable_crate_timings`].
+    ///
+    /// # Usage
+    /// ```no_run
+    /// use std::io::stdout;
+    ///
+    /// use ptx_builder::prelude::*;
+    /// # use ptx_builder::error::Result;
+    ///
+    /// # fn main() -> Result<()> {
+    /// if let BuildStatus::Success(output) = Builder::new(".")?.build()? {
+    ///     let report = output.report()?;
+    ///
+    ///     eprintln!("{} kernels in {} bytes", report.kernel_count, report.assembly_size);
+    ///     report.write_json(stdout())?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn report(&self) -> Result<BuildReport> {
+        let artifact = read(self.get_assembly_path()).context(BuildErrorKind::OtherError)?;
+
+        Ok(BuildReport {
+            duration: self.cargo_duration.unwrap_or_default(),
+            cached: self.cargo_duration.is_none(),
+            crate_timings: self.crate_timings.clone(),
+            assembly_size: artifact.len() as u64,
+            // binary code objects have no lines
+            assembly_lines: std::str::from_utf8(&artifact).map_or(0, |text| text.lines().count()),
+            kernel_count: self.builder.target.get_gpu().get_kernels(&artifact).len(),
+        })
+    }
+
+    /// Returns the size of the PTX assembly before and after it is compressed
+    /// for embedding, see
+    /// [`PtxSource::enable_compression`](crate::embed::PtxSource::enable_compression).
+    #[cfg(feature = "compression")]
+    pub fn compression_report(&self) -> Result<CompressionReport> {
+        let assembly = read(self.get_assembly_path()).context(BuildErrorKind::OtherError)?;
+
+        Ok(CompressionReport {
+            uncompressed_size: assembly.len() as u64,
+            compressed_size: crate::embed::compress(&assembly).len() as u64,
+        })
+    }
+
     /// Returns a list of crate dependencies.
     ///
     /// # Usage
@@ -555,7 +1946,7 @@ impl<'a> BuildOutput<'a> {
     fn get_deps_file_contents(&self) -> Result<String> {
         let crate_deps_path = self
             .output_path
-            .join(TARGET_NAME)
+            .join(self.builder.target.get_name())
             .join(self.builder.profile.to_string())
             .join(format!(
                 "{}.d",
@@ -577,6 +1968,51 @@ impl<'a> BuildOutput<'a> {
     }
 }
 
+#[cfg(feature = "async")]
+impl<'a> AsyncBuild<'a> {
+    /// Waits for the build to finish, discarding any further output lines.
+    pub async fn status(mut self) -> Result<BuildStatus<'a>> {
+        self.lines.close();
+
+        match (self.build.take(), self.status.take()) {
+            (Some(build), _) => build.await,
+            (None, Some(status)) => status,
+            (None, None) => unreachable!("the status is only taken once"),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl futures_core::Stream for AsyncBuild<'_> {
+    type Item = OutputLine;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<OutputLine>> {
+        let this = self.get_mut();
+
+        if let Some(build) = &mut this.build {
+            if let Poll::Ready(status) = build.as_mut().poll(cx) {
+                this.status = Some(status);
+                // drops the sender, which ends the stream once it is drained
+                this.build = None;
+            }
+        }
+
+        match this.lines.poll_recv(cx) {
+            Poll::Pending if this.build.is_none() => Poll::Ready(None),
+            poll => poll,
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl fmt::Debug for AsyncBuild<'_> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("AsyncBuild")
+            .field("finished", &self.build.is_none())
+            .finish_non_exhaustive()
+    }
+}
+
 impl fmt::Display for Profile {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -600,3 +2036,192 @@ impl std::ops::Deref for BuildCommand {
         }
     }
 }
+
+#[test]
+fn should_resolve_target_dir() {
+    let builder = Builder::new("tests/fixtures/sample-crate").unwrap();
+
+    assert_eq!(
+        builder.get_target_dir().unwrap(),
+        env::var_os("OUT_DIR").map_or_else(|| PathBuf::from(env!("OUT_DIR")), PathBuf::from)
+    );
+
+    let builder = builder.with_target_dir("target/device");
+
+    assert_eq!(
+        builder.get_target_dir().unwrap(),
+        env::current_dir().unwrap().join("target").join("device")
+    );
+}
+
+#[test]
+fn should_fingerprint_build_settings() {
+    const RUSTC_VERSION: &str = "rustc 1.101.0-nightly\nhost: x86_64-unknown-linux-gnu\n";
+
+    let builder = Builder::new("tests/fixtures/sample-crate").unwrap();
+    let fingerprint = builder.get_fingerprint(RUSTC_VERSION);
+
+    assert_eq!(fingerprint.len(), 16);
+    assert_eq!(builder.get_fingerprint(RUSTC_VERSION), fingerprint);
+
+    let builder = builder.set_features(["some-feature"]);
+    assert_ne!(builder.get_fingerprint(RUSTC_VERSION), fingerprint);
+
+    let features_fingerprint = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .set_features(["b-feature", "a-feature"])
+        .get_fingerprint(RUSTC_VERSION);
+    assert_eq!(
+        Builder::new("tests/fixtures/sample-crate")
+            .unwrap()
+            .set_features(["a-feature", "b-feature", "a-feature"])
+            .get_fingerprint(RUSTC_VERSION),
+        features_fingerprint
+    );
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_env("SOME_VAR", "1");
+    assert_ne!(builder.get_fingerprint(RUSTC_VERSION), fingerprint);
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .set_profile(Profile::Debug);
+    assert_ne!(builder.get_fingerprint(RUSTC_VERSION), fingerprint);
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .build_std(["core"]);
+    assert_ne!(builder.get_fingerprint(RUSTC_VERSION), fingerprint);
+
+    let builder = Builder::new("tests/fixtures/mixed-crate").unwrap();
+    assert_ne!(builder.get_fingerprint(RUSTC_VERSION), fingerprint);
+
+    let builder = Builder::new("tests/fixtures/sample-crate").unwrap();
+    assert_ne!(
+        builder.get_fingerprint("rustc 1.102.0-nightly\nhost: x86_64-unknown-linux-gnu\n"),
+        fingerprint
+    );
+}
+
+#[test]
+fn should_request_crate_timings_only_when_enabled() {
+    let json = MessageFormat::Json {
+        render_diagnostics: false,
+        short: false,
+        ansi: false,
+    };
+
+    let builder = Builder::new("tests/fixtures/sample-crate").unwrap();
+    assert!(!builder
+        .get_cargo_args("cdylib", &json)
+        .contains(&String::from("--timings=json")));
+
+    let builder = builder.enable_crate_timings();
+    assert!(builder
+        .get_cargo_args("cdylib", &json)
+        .contains(&String::from("--timings=json")));
+}
+
+#[test]
+fn should_use_json_messages_for_crate_timings() {
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .disable_colors();
+
+    assert_eq!(
+        builder.get_cargo_message_format(&MessageFormat::Human),
+        MessageFormat::Human
+    );
+
+    let builder = builder.enable_crate_timings();
+    let args = builder.get_cargo_args(
+        "cdylib",
+        &builder.get_cargo_message_format(&MessageFormat::Human),
+    );
+
+    assert!(args.contains(&String::from(
+        "--message-format=json,json-render-diagnostics"
+    )));
+    assert!(args.contains(&String::from("--timings=json")));
+
+    let args = builder.get_cargo_args(
+        "cdylib",
+        &builder.get_cargo_message_format(&MessageFormat::Short),
+    );
+
+    assert!(args.contains(&String::from(
+        "--message-format=json,json-render-diagnostics,json-diagnostic-short"
+    )));
+
+    let json = MessageFormat::Json {
+        render_diagnostics: false,
+        short: false,
+        ansi: true,
+    };
+    assert_eq!(builder.get_cargo_message_format(&json), json);
+}
+
+#[test]
+fn should_resolve_target_names() {
+    assert_eq!(Target::default().get_name(), "nvptx64-nvidia-cuda");
+    assert_eq!(Target::from("nvptx64-nvidia-cuda"), Target::default());
+
+    let target = Target::from("targets/nvptx64-custom.json");
+
+    assert_eq!(
+        target,
+        Target::Spec(PathBuf::from("targets/nvptx64-custom.json"))
+    );
+    assert_eq!(target.get_name(), "nvptx64-custom");
+    assert_eq!(
+        PathBuf::from(target.get_cargo_arg()),
+        env::current_dir()
+            .unwrap()
+            .join("targets")
+            .join("nvptx64-custom.json")
+    );
+}
+
+#[test]
+fn should_describe_gpu_targets() {
+    assert_eq!(Target::default().get_gpu(), GpuTarget::Nvptx);
+    assert_eq!(
+        Target::from(GpuTarget::Amdgpu),
+        Target::Builtin(String::from("amdgcn-amd-amdhsa"))
+    );
+    assert_eq!(Target::from(GpuTarget::Amdgpu).get_gpu(), GpuTarget::Amdgpu);
+    assert_eq!(
+        Target::from("tests/fixtures/nvptx64-custom.json").get_gpu(),
+        GpuTarget::Nvptx
+    );
+
+    assert!(GpuTarget::Nvptx.is_valid_target_cpu("sm_75"));
+    assert!(!GpuTarget::Nvptx.is_valid_target_cpu("gfx90a"));
+    assert!(GpuTarget::Amdgpu.is_valid_target_cpu("gfx90a"));
+    assert!(GpuTarget::Amdgpu.requires_target_cpu());
+}
+
+#[test]
+fn should_find_kernels_in_artifacts() {
+    let assembly = b"
+        .visible .entry the_kernel(
+            .param .u64 the_kernel_param_0
+        )
+        .visible .entry other_kernel()
+        .func helper()
+    ";
+
+    assert_eq!(
+        GpuTarget::Nvptx.get_kernels(assembly),
+        BTreeSet::from([String::from("other_kernel"), String::from("the_kernel")])
+    );
+
+    // the same symbols are part of both `.strtab` and `.dynstr`
+    let code_object = b"\x7fELF\0the_kernel\0the_kernel.kd\0helper\0the_kernel.kd\0";
+
+    assert_eq!(
+        GpuTarget::Amdgpu.get_kernels(code_object),
+        BTreeSet::from([String::from("the_kernel")])
+    );
+}
@@ -0,0 +1,490 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use colored::Colorize;
+
+use crate::{
+    builder::{Target, TARGET_NAME},
+    environment::cargo_home,
+    error::{BuildErrorKind, Error, Result},
+    executable::{CargoWith, Executable, ExecutableRunner, Rustc, Toolchain},
+};
+
+/// Environment diagnostics for building CUDA crates.
+///
+/// Runs every check, even if the previous ones have failed, and collects the
+/// outcomes into a [`DoctorReport`].
+///
+/// # Usage
+/// ```no_run
+/// use ptx_builder::doctor::Doctor;
+///
+/// let report = Doctor::new().with_crate(".").run();
+///
+/// if !report.is_healthy() {
+///     eprintln!("{report}");
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Doctor {
+    crate_path: Option<PathBuf>,
+    toolchain: Toolchain,
+}
+
+/// Environment diagnostic that is performed by the [`Doctor`].
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+pub enum CheckKind {
+    /// `cargo` is in `PATH` and fulfils the version requirement.
+    Cargo,
+
+    /// `rustc` is a nightly toolchain.
+    NightlyToolchain,
+
+    /// The standard library for the `nvptx64-nvidia-cuda` target is
+    /// installed.
+    Target,
+
+    /// The `llvm-bitcode-linker` component is installed.
+    BitcodeLinker,
+
+    /// The device crate's `.cargo/config.toml` selects the `llbc` linker
+    /// flavor.
+    LinkerFlavor,
+}
+
+/// Outcome of a single diagnostic.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum CheckStatus {
+    /// The check has passed, with details about what was found.
+    Passed(String),
+
+    /// The check has failed.
+    Failed {
+        /// What is wrong with the environment.
+        reason: String,
+        /// How the problem can be fixed.
+        hint: String,
+    },
+}
+
+/// Diagnostic together with its outcome.
+#[derive(Clone, Debug)]
+pub struct Check {
+    kind: CheckKind,
+    status: CheckStatus,
+}
+
+/// Outcomes of all diagnostics performed by the [`Doctor`].
+#[derive(Clone, Debug)]
+pub struct DoctorReport {
+    checks: Vec<Check>,
+}
+
+impl Doctor {
+    /// Creates a doctor which only checks the toolchain.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Additionally check the configuration of the device crate at `path`.
+    #[must_use]
+    pub fn with_crate<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.crate_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Check the specified toolchain instead of the one in `PATH`.
+    #[must_use]
+    pub fn with_toolchain<T: Into<Toolchain>>(mut self, toolchain: T) -> Self {
+        self.toolchain = toolchain.into();
+        self
+    }
+
+    /// Runs all diagnostics.
+    #[must_use]
+    pub fn run(&self) -> DoctorReport {
+        let toolchain = &self.toolchain;
+
+        let mut checks = vec![
+            Check::new(CheckKind::Cargo, check_cargo(toolchain)),
+            Check::new(
+                CheckKind::NightlyToolchain,
+                check_nightly_toolchain(toolchain),
+            ),
+            Check::new(CheckKind::Target, check_target(toolchain)),
+            Check::new(CheckKind::BitcodeLinker, check_bitcode_linker(toolchain)),
+        ];
+
+        if let Some(crate_path) = &self.crate_path {
+            checks.push(Check::new(
+                CheckKind::LinkerFlavor,
+                check_linker_flavor(crate_path),
+            ));
+        }
+
+        DoctorReport { checks }
+    }
+}
+
+impl Check {
+    fn new(kind: CheckKind, status: CheckStatus) -> Self {
+        Self { kind, status }
+    }
+
+    /// Returns which diagnostic was performed.
+    #[must_use]
+    pub fn kind(&self) -> CheckKind {
+        self.kind
+    }
+
+    /// Returns the outcome of the diagnostic.
+    #[must_use]
+    pub fn status(&self) -> &CheckStatus {
+        &self.status
+    }
+
+    /// Returns bool indicating whether the diagnostic has passed.
+    #[must_use]
+    pub fn is_passed(&self) -> bool {
+        matches!(self.status, CheckStatus::Passed(_))
+    }
+}
+
+impl DoctorReport {
+    /// Returns bool indicating whether all diagnostics have passed.
+    #[must_use]
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(Check::is_passed)
+    }
+
+    /// Returns all performed diagnostics in order.
+    #[must_use]
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Returns the outcome of a specific diagnostic, if it was performed.
+    #[must_use]
+    pub fn get(&self, kind: CheckKind) -> Option<&Check> {
+        self.checks.iter().find(|check| check.kind == kind)
+    }
+}
+
+impl fmt::Display for CheckKind {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckKind::Cargo => write!(fmt, "cargo version"),
+            CheckKind::NightlyToolchain => write!(fmt, "nightly toolchain"),
+            CheckKind::Target => write!(fmt, "{TARGET_NAME} target"),
+            CheckKind::BitcodeLinker => write!(fmt, "llvm-bitcode-linker component"),
+            CheckKind::LinkerFlavor => write!(fmt, "linker flavor"),
+        }
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (i, check) in self.checks.iter().enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+
+            match &check.status {
+                CheckStatus::Passed(details) => write!(
+                    fmt,
+                    "{} {}: {}",
+                    "[ok]".green().bold(),
+                    check.kind.to_string().bold(),
+                    details
+                )?,
+
+                CheckStatus::Failed { reason, hint } => write!(
+                    fmt,
+                    "{} {}: {}\n     {}",
+                    "[error]".red().bold(),
+                    check.kind.to_string().bold(),
+                    reason,
+                    hint.underline()
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn check_cargo(toolchain: &Toolchain) -> CheckStatus {
+    let cargo = CargoWith::new(toolchain.clone());
+
+    let current = match cargo.get_current_version() {
+        Ok(current) => current,
+        Err(error) => return failed_with_error(&error, cargo.get_verification_hint()),
+    };
+
+    match cargo.get_required_version() {
+        Some(required) if !required.matches(&current) => CheckStatus::Failed {
+            reason: format!("cargo {current} does not fulfil '{required}'"),
+            hint: cargo.get_version_hint(),
+        },
+
+        _ => CheckStatus::Passed(format!("cargo {current}")),
+    }
+}
+
+fn check_nightly_toolchain(toolchain: &Toolchain) -> CheckStatus {
+    let version = match rustc(toolchain, &["-V"]) {
+        Ok(version) => version.trim().to_string(),
+        Err(error) => return failed_with_error(&error, Rustc::default().get_verification_hint()),
+    };
+
+    if version.contains("-nightly") || version.contains("-dev") {
+        CheckStatus::Passed(version)
+    } else {
+        CheckStatus::Failed {
+            reason: format!("'{version}' is not a nightly toolchain"),
+            hint: String::from(
+                "Please switch to a nightly toolchain, e.g. with `rustup override set nightly`",
+            ),
+        }
+    }
+}
+
+fn check_target(toolchain: &Toolchain) -> CheckStatus {
+    match find_target_libdir(toolchain, &Target::default()) {
+        Ok((target_libdir, true)) => CheckStatus::Passed(target_libdir.display().to_string()),
+        Ok((_, false)) => CheckStatus::Failed {
+            reason: format!("the `{TARGET_NAME}` target is not installed"),
+            hint: target_hint(&Target::default()),
+        },
+        Err(error) => failed_with_error(&error, Rustc::default().get_verification_hint()),
+    }
+}
+
+fn check_bitcode_linker(toolchain: &Toolchain) -> CheckStatus {
+    let linker = find_sysroot(toolchain).and_then(|sysroot| {
+        let verbose_version = rustc(toolchain, &["-vV"])?;
+        Ok(find_bitcode_linker(&sysroot, get_host(&verbose_version)))
+    });
+
+    match linker {
+        Ok(Some(path)) => CheckStatus::Passed(path.display().to_string()),
+        Ok(None) => CheckStatus::Failed {
+            reason: String::from("the `llvm-bitcode-linker` component is not installed"),
+            hint: bitcode_linker_hint(),
+        },
+        Err(error) => failed_with_error(&error, Rustc::default().get_verification_hint()),
+    }
+}
+
+/// Returns the target libdir and whether it contains a prebuilt `core`.
+pub(crate) fn find_target_libdir(
+    toolchain: &Toolchain,
+    target: &Target,
+) -> Result<(PathBuf, bool)> {
+    let target_arg = target.get_cargo_arg();
+    let mut args = vec!["--print", "target-libdir", "--target", &target_arg];
+
+    if let Target::Spec(_) = target {
+        args.insert(0, "-Zunstable-options");
+    }
+
+    let target_libdir = PathBuf::from(rustc(toolchain, &args)?.trim());
+
+    let has_core = fs::read_dir(&target_libdir).is_ok_and(|entries| {
+        entries.filter_map(std::result::Result::ok).any(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+
+            name.starts_with("libcore-") && name.ends_with(".rlib")
+        })
+    });
+
+    Ok((target_libdir, has_core))
+}
+
+/// Returns the sysroot of the toolchain.
+pub(crate) fn find_sysroot(toolchain: &Toolchain) -> Result<PathBuf> {
+    Ok(PathBuf::from(
+        rustc(toolchain, &["--print", "sysroot"])?.trim(),
+    ))
+}
+
+/// Returns the host triple from the output of `rustc -vV`.
+pub(crate) fn get_host(verbose_version: &str) -> &str {
+    verbose_version
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .unwrap_or_default()
+}
+
+/// Returns the path to the `llvm-bitcode-linker` of the `host` in the
+/// `sysroot`, if installed.
+pub(crate) fn find_bitcode_linker(sysroot: &Path, host: &str) -> Option<PathBuf> {
+    let bin_dir = sysroot.join("lib").join("rustlib").join(host).join("bin");
+    let linker_name = format!("llvm-bitcode-linker{}", env::consts::EXE_SUFFIX);
+
+    [bin_dir.join("self-contained"), bin_dir]
+        .into_iter()
+        .map(|dir| dir.join(&linker_name))
+        .find(|path| path.is_file())
+}
+
+/// Returns the path to the standard library sources in the `sysroot`, which
+/// `-Z build-std` needs, if installed.
+pub(crate) fn find_rust_src(sysroot: &Path) -> Option<PathBuf> {
+    let library = sysroot
+        .join("lib")
+        .join("rustlib")
+        .join("src")
+        .join("rust")
+        .join("library");
+
+    Some(library).filter(|library| library.join("core").is_dir())
+}
+
+pub(crate) fn target_hint(target: &Target) -> String {
+    match target {
+        Target::Builtin(name) if target.get_gpu().has_prebuilt_core() => {
+            format!("Please install it with `rustup target add {name}`")
+        }
+
+        Target::Builtin(_) => String::from(
+            "The target has no prebuilt `core`, please build it with `Builder::build_std`",
+        ),
+
+        Target::Spec(_) => String::from(
+            "Custom targets have no prebuilt `core`, please build it with `Builder::build_std`",
+        ),
+    }
+}
+
+pub(crate) fn rust_src_hint() -> String {
+    String::from("Please install it with `rustup component add rust-src`")
+}
+
+pub(crate) fn bitcode_linker_hint() -> String {
+    String::from("Please install it with `rustup component add llvm-bitcode-linker llvm-tools`")
+}
+
+fn check_linker_flavor(crate_path: &Path) -> CheckStatus {
+    let crate_path =
+        env::current_dir().map_or_else(|_| crate_path.into(), |cwd| cwd.join(crate_path));
+
+    let configs = crate_path
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home())
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")])
+        .filter(|path| path.is_file());
+
+    for config in configs {
+        let Some(contents) = fs::read_to_string(&config)
+            .ok()
+            .and_then(|contents| toml::from_str::<toml::Value>(&contents).ok())
+        else {
+            continue;
+        };
+
+        if config_sets_linker_flavor(&contents) {
+            return CheckStatus::Passed(config.display().to_string());
+        }
+    }
+
+    CheckStatus::Failed {
+        reason: format!(
+            "no `.cargo/config.toml` selects `-Clinker-flavor=llbc` for the `{TARGET_NAME}` target"
+        ),
+        hint: format!(
+            "Please add `[target.{TARGET_NAME}] rustflags = [\"-Zunstable-options\", \
+             \"-Clinker-flavor=llbc\"]` to the device crate's `.cargo/config.toml`"
+        ),
+    }
+}
+
+fn config_sets_linker_flavor(config: &toml::Value) -> bool {
+    [
+        config
+            .get("target")
+            .and_then(|target| target.get(TARGET_NAME)),
+        config.get("build"),
+    ]
+    .into_iter()
+    .flatten()
+    .filter_map(|table| table.get("rustflags"))
+    .any(|rustflags| {
+        let rustflags = match rustflags {
+            toml::Value::String(rustflags) => rustflags.clone(),
+            toml::Value::Array(rustflags) => rustflags
+                .iter()
+                .filter_map(toml::Value::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+            _ => String::new(),
+        };
+
+        rustflags
+            .replace("-C ", "-C")
+            .split_whitespace()
+            .any(|flag| flag == "-Clinker-flavor=llbc")
+    })
+}
+
+fn rustc(toolchain: &Toolchain, args: &[&str]) -> Result<String> {
+    ExecutableRunner::new(Rustc::new(toolchain.clone()))
+        .with_args(args)
+        .run()
+        .map(|output| output.stdout)
+}
+
+fn failed_with_error(error: &Error, hint: String) -> CheckStatus {
+    let reason = match error.kind() {
+        BuildErrorKind::CommandNotFound { command, .. } => {
+            format!("'{command}' was not found in PATH")
+        }
+        kind => kind.to_string(),
+    };
+
+    CheckStatus::Failed { reason, hint }
+}
+
+#[test]
+fn should_find_linker_flavor_in_config() {
+    let config: toml::Value = toml::from_str(
+        r#"
+        [target.nvptx64-nvidia-cuda]
+        rustflags = ["-Zunstable-options", "-Clinker-flavor=llbc"]
+        "#,
+    )
+    .unwrap();
+
+    assert!(config_sets_linker_flavor(&config));
+
+    let config: toml::Value = toml::from_str(
+        r#"
+        [build]
+        rustflags = "-Zunstable-options -C linker-flavor=llbc"
+        "#,
+    )
+    .unwrap();
+
+    assert!(config_sets_linker_flavor(&config));
+}
+
+#[test]
+fn should_not_find_linker_flavor_for_other_targets() {
+    let config: toml::Value = toml::from_str(
+        r#"
+        [target.x86_64-unknown-linux-gnu]
+        rustflags = ["-Clinker-flavor=llbc"]
+
+        [target.nvptx64-nvidia-cuda]
+        rustflags = ["-Ctarget-cpu=sm_75"]
+        "#,
+    )
+    .unwrap();
+
+    assert!(!config_sets_linker_flavor(&config));
+}
@@ -0,0 +1,406 @@
+#[cfg(feature = "compression")]
+use std::ffi::CString;
+use std::{
+    fmt::Write as _,
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+use crate::{
+    builder::BuildOutput,
+    error::{BuildErrorKind, Result, ResultExt},
+    fatbin::get_ptx_arch,
+};
+
+/// Generated Rust source, which embeds the PTX assembly as C strings.
+///
+/// The host crate includes the source instead of the assembly path, so that
+/// no absolute build paths end up in its binary:
+/// ```ignore
+/// include!(concat!(env!("OUT_DIR"), "/ptx.rs"));
+///
+/// let module = cuda_load_module(PTX)?;
+/// ```
+///
+/// The source defines `PTX`, the assembly which was added first, and with
+/// [`PtxSource::enable_arch_table`] also a `PTX_SM_XX` static per GPU
+/// architecture and a `ptx_for_arch(arch: &str)` function to look them up.
+///
+/// # Usage in `build.rs`
+/// ```no_run
+/// use std::{env, path::Path};
+///
+/// use ptx_builder::{embed::PtxSource, prelude::*};
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// let out_dir = env::var("OUT_DIR").unwrap();
+///
+/// if let BuildStatus::Success(output) = Builder::new(".")?.build()? {
+///     PtxSource::new()
+///         .with_output(&output)?
+///         .write(Path::new(&out_dir).join("ptx.rs"))?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PtxSource {
+    assemblies: Vec<(String, String)>,
+    arch_table: bool,
+    #[cfg(feature = "compression")]
+    compression: bool,
+}
+
+impl PtxSource {
+    /// Creates a source without any PTX assembly, where `PTX` is empty.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the PTX `assembly`, which was generated for the GPU architecture
+    /// `arch` (e.g. `sm_75`), replacing any assembly for the same `arch`.
+    #[must_use]
+    pub fn with_ptx<S: Into<String>, A: Into<String>>(mut self, arch: S, assembly: A) -> Self {
+        let arch = arch.into();
+        let assembly = assembly.into();
+
+        match self.assemblies.iter_mut().find(|(known, _)| *known == arch) {
+            Some(existing) => existing.1 = assembly,
+            None => self.assemblies.push((arch, assembly)),
+        }
+
+        self
+    }
+
+    /// Adds the PTX assembly of a build, whose architecture is read from its
+    /// `.target` directive.
+    pub fn with_output(self, output: &BuildOutput) -> Result<Self> {
+        let assembly =
+            read_to_string(output.get_assembly_path()).context(BuildErrorKind::OtherError)?;
+        let arch = String::from(get_ptx_arch(&assembly)?);
+
+        Ok(self.with_ptx(arch, assembly))
+    }
+
+    /// Also generate a static per GPU architecture and the `ptx_for_arch`
+    /// lookup function.
+    #[must_use]
+    pub fn enable_arch_table(mut self) -> Self {
+        self.arch_table = true;
+        self
+    }
+
+    /// Embed the PTX assembly compressed with zlib, instead of as C strings.
+    ///
+    /// The source then defines `PTX_DEFLATE` and a `decompress()` function,
+    /// which returns the assembly as a `CString`, and with
+    /// [`PtxSource::enable_arch_table`] also a `PTX_SM_XX_DEFLATE` static per
+    /// GPU architecture and a `decompress_for_arch(arch: &str)` function.
+    ///
+    /// The host crate has to depend on `miniz_oxide` to decompress it.
+    #[cfg(feature = "compression")]
+    #[must_use]
+    pub fn enable_compression(mut self) -> Self {
+        self.compression = true;
+        self
+    }
+
+    /// Returns the generated Rust source.
+    pub fn to_source(&self) -> Result<String> {
+        #[cfg(feature = "compression")]
+        if self.compression {
+            return self.to_compressed_source();
+        }
+
+        let mut source = String::from("// Generated by `ptx-builder`, do not edit.\n");
+
+        // with the table, `PTX` refers to a per-arch static instead of a copy
+        let default = match (self.arch_table, self.assemblies.first()) {
+            (true, Some((arch, _))) => Self::get_static_name(arch),
+            (false, Some((_, assembly))) => Self::to_c_string_literal(assembly)?,
+            (_, None) => String::from("c\"\""),
+        };
+
+        let _ = write!(
+            source,
+            "\n/// PTX assembly of the device crate.\n\
+             pub static PTX: &::core::ffi::CStr = {default};\n"
+        );
+
+        if !self.arch_table {
+            return Ok(source);
+        }
+
+        for (arch, assembly) in &self.assemblies {
+            let _ = write!(
+                source,
+                "\n/// PTX assembly for the GPU architecture `{arch}`.\n\
+                 pub static {}: &::core::ffi::CStr = {};\n",
+                Self::get_static_name(arch),
+                Self::to_c_string_literal(assembly)?
+            );
+        }
+
+        source.push_str(
+            "\n/// Returns the PTX assembly for the GPU architecture `arch`, e.g. `\"sm_75\"`.\n\
+             #[must_use]\n\
+             pub fn ptx_for_arch(arch: &str) -> ::core::option::Option<&'static ::core::ffi::CStr> {\n    \
+                 match arch {\n",
+        );
+
+        for (arch, _) in &self.assemblies {
+            let _ = writeln!(
+                source,
+                "        {arch:?} => ::core::option::Option::Some({}),",
+                Self::get_static_name(arch)
+            );
+        }
+
+        source.push_str("        _ => ::core::option::Option::None,\n    }\n}\n");
+
+        Ok(source)
+    }
+
+    #[cfg(feature = "compression")]
+    fn to_compressed_source(&self) -> Result<String> {
+        let mut source = String::from("// Generated by `ptx-builder`, do not edit.\n");
+
+        // with the table, `PTX_DEFLATE` refers to a per-arch static instead
+        let default = match (self.arch_table, self.assemblies.first()) {
+            (true, Some((arch, _))) => format!("{}_DEFLATE", Self::get_static_name(arch)),
+            (false, Some((_, assembly))) => Self::to_compressed_literal(assembly)?,
+            (_, None) => Self::to_compressed_literal("")?,
+        };
+
+        let _ = write!(
+            source,
+            "\n/// zlib-compressed PTX assembly of the device crate, see [`decompress`].\n\
+             pub static PTX_DEFLATE: &[u8] = {default};\n\
+             \n\
+             /// Decompresses the PTX assembly of the device crate.\n\
+             #[must_use]\n\
+             pub fn decompress() -> ::std::ffi::CString {{\n    \
+                 decompress_ptx(PTX_DEFLATE)\n\
+             }}\n"
+        );
+
+        if self.arch_table {
+            for (arch, assembly) in &self.assemblies {
+                let _ = write!(
+                    source,
+                    "\n/// zlib-compressed PTX assembly for the GPU architecture `{arch}`.\n\
+                     pub static {}_DEFLATE: &[u8] = {};\n",
+                    Self::get_static_name(arch),
+                    Self::to_compressed_literal(assembly)?
+                );
+            }
+
+            source.push_str(
+                "\n/// Decompresses the PTX assembly for the GPU architecture `arch`, e.g. \
+                 `\"sm_75\"`.\n\
+                 #[must_use]\n\
+                 pub fn decompress_for_arch(arch: &str) -> ::core::option::Option<::std::ffi::CString> {\n    \
+                     match arch {\n",
+            );
+
+            for (arch, _) in &self.assemblies {
+                let _ = writeln!(
+                    source,
+                    "        {arch:?} => ::core::option::Option::Some(decompress_ptx({}_DEFLATE)),",
+                    Self::get_static_name(arch)
+                );
+            }
+
+            source.push_str("        _ => ::core::option::Option::None,\n    }\n}\n");
+        }
+
+        source.push_str(
+            "\nfn decompress_ptx(compressed: &[u8]) -> ::std::ffi::CString {\n    \
+                 let assembly = ::miniz_oxide::inflate::decompress_to_vec_zlib(compressed)\n        \
+                     .expect(\"The embedded PTX assembly is corrupted\");\n\
+             \n    \
+                 ::std::ffi::CString::new(assembly).expect(\"The embedded PTX assembly contains a NUL byte\")\n\
+             }\n",
+        );
+
+        Ok(source)
+    }
+
+    /// Writes the generated Rust source to `path`.
+    ///
+    /// An unchanged file is not rewritten, so the host crate is not
+    /// recompiled needlessly.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let source = self.to_source()?;
+
+        if read_to_string(path.as_ref()).is_ok_and(|existing| existing == source) {
+            return Ok(());
+        }
+
+        write(path, source).context(BuildErrorKind::OtherError)?;
+
+        Ok(())
+    }
+
+    /// Returns e.g. `PTX_SM_75` for `sm_75`.
+    fn get_static_name(arch: &str) -> String {
+        let arch = arch
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+
+        format!("PTX_{arch}")
+    }
+
+    /// Returns a `b"..."` literal of the compressed assembly.
+    #[cfg(feature = "compression")]
+    fn to_compressed_literal(assembly: &str) -> Result<String> {
+        Self::check_nul_bytes(assembly)?;
+
+        let mut literal = String::from("b\"");
+
+        for (index, chunk) in compress(assembly.as_bytes()).chunks(32).enumerate() {
+            if index > 0 {
+                literal.push_str("\\\n");
+            }
+
+            for byte in chunk {
+                let _ = write!(literal, "\\x{byte:02x}");
+            }
+        }
+
+        literal.push('"');
+
+        Ok(literal)
+    }
+
+    /// Returns a `c"..."` literal with one source line per assembly line.
+    fn to_c_string_literal(assembly: &str) -> Result<String> {
+        Self::check_nul_bytes(assembly)?;
+
+        let mut literal = String::from("c\"");
+
+        for (index, line) in assembly.split_inclusive('\n').enumerate() {
+            // a line continuation skips the leading whitespace of the next line
+            if index > 0 {
+                literal.push_str("\\\n");
+            }
+
+            let indentation = line.len() - line.trim_start_matches(' ').len();
+            literal.push_str(&"\\x20".repeat(indentation));
+
+            for c in line[indentation..].chars() {
+                let _ = write!(literal, "{}", c.escape_default());
+            }
+        }
+
+        literal.push('"');
+
+        Ok(literal)
+    }
+
+    fn check_nul_bytes(assembly: &str) -> Result<()> {
+        if assembly.contains('\0') {
+            bail!(BuildErrorKind::InternalError(String::from(
+                "Unable to embed PTX assembly, which contains a NUL byte",
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Compresses the PTX assembly with zlib, as it is embedded with
+/// [`PtxSource::enable_compression`].
+#[cfg(feature = "compression")]
+pub(crate) fn compress(assembly: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec_zlib(assembly, 9)
+}
+
+/// Decompresses PTX assembly, which was embedded with
+/// [`PtxSource::enable_compression`].
+///
+/// The generated `decompress()` function does the same in the host crate.
+#[cfg(feature = "compression")]
+pub fn decompress(compressed: &[u8]) -> Result<CString> {
+    let assembly = miniz_oxide::inflate::decompress_to_vec_zlib(compressed).map_err(|error| {
+        BuildErrorKind::InternalError(format!("Unable to decompress PTX assembly: {error}"))
+    })?;
+
+    CString::new(assembly).context(BuildErrorKind::OtherError)
+}
+
+#[test]
+fn should_escape_ptx_assembly() {
+    let source = PtxSource::new()
+        .with_ptx(
+            "sm_75",
+            ".version 7.5\n  .target sm_75\n\tld.param.u64 \"x\";\n",
+        )
+        .to_source()
+        .unwrap();
+
+    assert_eq!(
+        source,
+        "// Generated by `ptx-builder`, do not edit.\n\
+         \n\
+         /// PTX assembly of the device crate.\n\
+         pub static PTX: &::core::ffi::CStr = c\".version 7.5\\n\\\n\
+         \\x20\\x20.target sm_75\\n\\\n\
+         \\tld.param.u64 \\\"x\\\";\\n\";\n"
+    );
+
+    assert!(PtxSource::new()
+        .with_ptx("sm_75", "\0")
+        .to_source()
+        .is_err());
+}
+
+#[test]
+fn should_generate_arch_table() {
+    let source = PtxSource::new()
+        .with_ptx("sm_75", "a")
+        .with_ptx("sm_86", "b")
+        .with_ptx("sm_75", "c")
+        .enable_arch_table()
+        .to_source()
+        .unwrap();
+
+    assert!(source.contains("pub static PTX: &::core::ffi::CStr = PTX_SM_75;"));
+    assert!(source.contains("pub static PTX_SM_75: &::core::ffi::CStr = c\"c\";"));
+    assert!(source.contains("pub static PTX_SM_86: &::core::ffi::CStr = c\"b\";"));
+    assert!(source.contains("\"sm_86\" => ::core::option::Option::Some(PTX_SM_86),"));
+    assert!(source.contains("_ => ::core::option::Option::None,"));
+}
+
+#[cfg(feature = "compression")]
+#[test]
+fn should_compress_ptx_assembly() {
+    let assembly = ".version 7.5\n.target sm_75\n".repeat(100);
+
+    let compressed = compress(assembly.as_bytes());
+    assert!(compressed.len() < assembly.len());
+    assert_eq!(decompress(&compressed).unwrap().to_str().unwrap(), assembly);
+
+    let source = PtxSource::new()
+        .with_ptx("sm_75", assembly)
+        .enable_arch_table()
+        .enable_compression()
+        .to_source()
+        .unwrap();
+
+    assert!(source.contains("pub static PTX_DEFLATE: &[u8] = PTX_SM_75_DEFLATE;"));
+    assert!(source.contains("pub static PTX_SM_75_DEFLATE: &[u8] = b\"\\x78"));
+    assert!(source.contains("pub fn decompress() -> ::std::ffi::CString {"));
+    assert!(source
+        .contains("\"sm_75\" => ::core::option::Option::Some(decompress_ptx(PTX_SM_75_DEFLATE)),"));
+    assert!(!source.contains("::core::ffi::CStr"));
+}
@@ -0,0 +1,148 @@
+use std::{env, ffi::OsStr, path::PathBuf};
+
+/// Variables which `cargo` sets for the host crate's build script, or which
+/// configure the host build, and which would leak into the device build.
+const BUILD_SCRIPT_ENV: &[&str] = &[
+    "TARGET",
+    "HOST",
+    "NUM_JOBS",
+    "OPT_LEVEL",
+    "DEBUG",
+    "PROFILE",
+    "OUT_DIR",
+    "RUSTC",
+    "RUSTDOC",
+    "RUSTC_LINKER",
+    "RUSTC_WRAPPER",
+    "RUSTC_WORKSPACE_WRAPPER",
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_TARGET_DIR",
+    "CARGO_MAKEFLAGS",
+    "CARGO_MANIFEST_DIR",
+    "CARGO_MANIFEST_PATH",
+    "CARGO_MANIFEST_LINKS",
+    "CARGO_PRIMARY_PACKAGE",
+    "CARGO_CRATE_NAME",
+    "CARGO_CFG_*",
+    "CARGO_FEATURE_*",
+    "CARGO_PKG_*",
+    "DEP_*",
+];
+
+/// Policy for the environment variables that the inner `cargo` invocation
+/// inherits from the current process.
+///
+/// When the builder runs from a host `build.rs`, the environment contains
+/// variables like `TARGET`, `RUSTC_WRAPPER` or `CARGO_ENCODED_RUSTFLAGS`,
+/// which describe the *host* build and would be picked up by the device
+/// build. By default, these are stripped, while everything else is passed
+/// through.
+///
+/// Patterns are either exact variable names or prefixes ending with `*`.
+/// Allowed patterns take precedence over denied ones, and variables which
+/// are explicitly set with
+/// [`Builder::with_env`](crate::builder::Builder::with_env) are always passed.
+///
+/// # Usage
+/// ``` no_run
+/// use ptx_builder::{environment::EnvPolicy, prelude::*};
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// Builder::new(".")?
+///     .with_env_policy(EnvPolicy::new().allow("RUSTC_WRAPPER").deny("MY_HOST_*"))
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug)]
+pub struct EnvPolicy {
+    allowed: Vec<String>,
+    denied: Vec<String>,
+}
+
+impl EnvPolicy {
+    /// Creates the default policy, which strips the host build script
+    /// environment.
+    #[must_use]
+    pub fn new() -> Self {
+        EnvPolicy {
+            allowed: Vec::new(),
+            denied: BUILD_SCRIPT_ENV.iter().map(|&name| name.into()).collect(),
+        }
+    }
+
+    /// Creates a policy which passes through the entire environment.
+    #[must_use]
+    pub fn inherit_all() -> Self {
+        EnvPolicy {
+            allowed: Vec::new(),
+            denied: Vec::new(),
+        }
+    }
+
+    /// Passes through variables that match `pattern`, even if denied.
+    #[must_use]
+    pub fn allow<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.allowed.push(pattern.into());
+        self
+    }
+
+    /// Strips variables that match `pattern`.
+    #[must_use]
+    pub fn deny<S: Into<String>>(mut self, pattern: S) -> Self {
+        self.denied.push(pattern.into());
+        self
+    }
+
+    pub(crate) fn allowed(&self) -> &[String] {
+        &self.allowed
+    }
+
+    pub(crate) fn denied(&self) -> &[String] {
+        &self.denied
+    }
+
+    /// Returns bool indicating whether the variable is stripped.
+    #[must_use]
+    pub fn is_denied<K: AsRef<OsStr>>(&self, key: K) -> bool {
+        let Some(key) = key.as_ref().to_str() else {
+            return false;
+        };
+
+        let matches = |pattern: &String| match pattern.strip_suffix('*') {
+            Some(prefix) => key.starts_with(prefix),
+            None => key == pattern,
+        };
+
+        self.denied.iter().any(matches) && !self.allowed.iter().any(matches)
+    }
+}
+
+impl Default for EnvPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns the directory of the cargo installation, i.e. `CARGO_HOME` or
+/// `~/.cargo` by default.
+pub(crate) fn cargo_home() -> Option<PathBuf> {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".cargo")))
+}
+
+/// Returns the directory of the rustup installation, i.e. `RUSTUP_HOME` or
+/// `~/.rustup` by default.
+pub(crate) fn rustup_home() -> Option<PathBuf> {
+    env::var_os("RUSTUP_HOME")
+        .map(PathBuf::from)
+        .or_else(|| home_dir().map(|home| home.join(".rustup")))
+}
+
+#[allow(deprecated)] // only deprecated before Rust 1.86
+fn home_dir() -> Option<PathBuf> {
+    env::home_dir()
+}
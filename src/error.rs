@@ -1,4 +1,4 @@
-use std::{fmt, path::PathBuf};
+use std::{fmt, path::PathBuf, time::Duration};
 
 use colored::Colorize;
 use semver::{Version, VersionReq};
@@ -106,20 +106,54 @@ pub enum BuildErrorKind {
         required: VersionReq,
         hint: String,
     },
+    TargetNotInstalled {
+        target: String,
+        hint: String,
+    },
+    ComponentNotInstalled {
+        component: String,
+        hint: String,
+    },
+    InvalidTargetCpu {
+        target: String,
+        target_cpu: Option<String>,
+        hint: String,
+    },
+
+    LockTimeout {
+        path: PathBuf,
+        timeout: Duration,
+    },
+    TimedOut {
+        command: String,
+        timeout: Duration,
+        stdout: String,
+        stderr: String,
+    },
+    Cancelled {
+        command: String,
+        stdout: String,
+        stderr: String,
+    },
 
     InvalidCratePath(PathBuf),
+    InvalidMetadata(String),
     BuildFailed(Vec<String>),
     InvalidCrateType(String),
     MissingCrateType,
+    MissingKernels(Vec<String>),
     InternalError(String),
     OtherError,
 }
 
 impl fmt::Display for BuildErrorKind {
+    #[allow(clippy::too_many_lines)]
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         use BuildErrorKind::{
-            BuildFailed, CommandFailed, CommandNotFound, CommandVersionNotFulfilled, InternalError,
-            InvalidCratePath, InvalidCrateType, MissingCrateType, OtherError,
+            BuildFailed, Cancelled, CommandFailed, CommandNotFound, CommandVersionNotFulfilled,
+            ComponentNotInstalled, InternalError, InvalidCratePath, InvalidCrateType,
+            InvalidMetadata, InvalidTargetCpu, LockTimeout, MissingCrateType, MissingKernels,
+            OtherError, TargetNotInstalled, TimedOut,
         };
 
         match self {
@@ -157,6 +191,73 @@ impl fmt::Display for BuildErrorKind {
                 hint.underline(),
             ),
 
+            TargetNotInstalled { target, hint } => write!(
+                fmt,
+                "Target is not installed: '{}'. {}.",
+                target.bold(),
+                hint.underline()
+            ),
+
+            ComponentNotInstalled { component, hint } => write!(
+                fmt,
+                "Toolchain component is not installed: '{}'. {}.",
+                component.bold(),
+                hint.underline()
+            ),
+
+            InvalidTargetCpu {
+                target,
+                target_cpu: Some(target_cpu),
+                hint,
+            } => write!(
+                fmt,
+                "Invalid target CPU for '{}': '{}'. {}.",
+                target.bold(),
+                target_cpu,
+                hint.underline()
+            ),
+
+            InvalidTargetCpu {
+                target,
+                target_cpu: None,
+                hint,
+            } => write!(
+                fmt,
+                "Target CPU is required for '{}'. {}.",
+                target.bold(),
+                hint.underline()
+            ),
+
+            LockTimeout { path, timeout } => write!(
+                fmt,
+                "{}: '{}' is still locked by another build after {:?}",
+                "Unable to lock the build directory".bold(),
+                path.display(),
+                timeout
+            ),
+
+            TimedOut {
+                command,
+                timeout,
+                stderr,
+                ..
+            } => write!(
+                fmt,
+                "Command timed out: '{}' was killed after {:?} with output:\n{}",
+                command.bold(),
+                timeout,
+                stderr.trim(),
+            ),
+
+            Cancelled {
+                command, stderr, ..
+            } => write!(
+                fmt,
+                "Command cancelled: '{}' was killed with output:\n{}",
+                command.bold(),
+                stderr.trim(),
+            ),
+
             InvalidCratePath(path) => write!(
                 fmt,
                 "{}: {}",
@@ -164,6 +265,13 @@ impl fmt::Display for BuildErrorKind {
                 path.display()
             ),
 
+            InvalidMetadata(message) => write!(
+                fmt,
+                "{}: {}",
+                "Invalid `[package.metadata.ptx-builder]` configuration".bold(),
+                message
+            ),
+
             BuildFailed(lines) => write!(
                 fmt,
                 "{}\n{}",
@@ -184,6 +292,13 @@ impl fmt::Display for BuildErrorKind {
                 "Missing CrateType".bold()
             ),
 
+            MissingKernels(kernels) => write!(
+                fmt,
+                "{}: {}",
+                "Expected kernels are missing from the PTX assembly".bold(),
+                kernels.join(", ")
+            ),
+
             InternalError(message) => write!(fmt, "{}: {}", "Internal error".bold(), message),
             OtherError => write!(fmt, "Other error"),
         }
@@ -0,0 +1,199 @@
+use std::{path::PathBuf, sync::LazyLock, time::Duration};
+
+use regex::Regex;
+use serde_json::Value;
+
+/// Progress of a device crate build.
+///
+/// The events are derived from the JSON message stream of `cargo`, see
+/// [`Builder::build_with_events`](crate::builder::Builder::build_with_events).
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum BuildEvent {
+    /// `cargo` has started to compile a package.
+    Compiling {
+        /// Name of the package.
+        package: String,
+        /// Version of the package.
+        version: String,
+    },
+
+    /// `rustc` has reported an error, warning or note.
+    Diagnostic {
+        /// Severity, e.g. `"error"` or `"warning"`.
+        level: String,
+        /// Primary message of the diagnostic.
+        message: String,
+        /// The diagnostic as `rustc` would have printed it.
+        rendered: Option<String>,
+    },
+
+    /// `rustc` has written an output file.
+    ArtifactReady {
+        /// Opaque `cargo` identifier of the package.
+        package_id: String,
+        /// Path of the output file.
+        path: PathBuf,
+    },
+
+    /// The build script of a package has been run.
+    BuildScriptExecuted {
+        /// Opaque `cargo` identifier of the package.
+        package_id: String,
+        /// `OUT_DIR` of the build script.
+        out_dir: PathBuf,
+    },
+
+    /// The build has succeeded.
+    Finished {
+        /// Time since the build has been started.
+        duration: Duration,
+    },
+}
+
+impl BuildEvent {
+    /// Parses a JSON message, which `cargo` prints to stdout.
+    ///
+    /// Every file of a compiled package is reported as a separate event.
+    pub(crate) fn from_json_message(line: &str) -> Vec<Self> {
+        let Ok(message) = serde_json::from_str::<Value>(line) else {
+            return Vec::new();
+        };
+
+        let string = |value: &Value| value.as_str().map(String::from);
+
+        match message["reason"].as_str() {
+            Some("compiler-message") => {
+                let diagnostic = &message["message"];
+
+                match (string(&diagnostic["level"]), string(&diagnostic["message"])) {
+                    (Some(level), Some(message)) => vec![BuildEvent::Diagnostic {
+                        level,
+                        message,
+                        rendered: string(&diagnostic["rendered"]),
+                    }],
+
+                    _ => Vec::new(),
+                }
+            }
+
+            Some("compiler-artifact") => {
+                let Some(package_id) = string(&message["package_id"]) else {
+                    return Vec::new();
+                };
+
+                message["filenames"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .map(|path| BuildEvent::ArtifactReady {
+                        package_id: package_id.clone(),
+                        path: PathBuf::from(path),
+                    })
+                    .collect()
+            }
+
+            Some("build-script-executed") => {
+                match (string(&message["package_id"]), string(&message["out_dir"])) {
+                    (Some(package_id), Some(out_dir)) => vec![BuildEvent::BuildScriptExecuted {
+                        package_id,
+                        out_dir: PathBuf::from(out_dir),
+                    }],
+
+                    _ => Vec::new(),
+                }
+            }
+
+            _ => Vec::new(),
+        }
+    }
+
+    /// Parses a status line, which `cargo` prints to stderr.
+    ///
+    /// The JSON messages do not report when a package starts to compile.
+    pub(crate) fn from_status_line(line: &str) -> Option<Self> {
+        static COMPILING_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"^\s*(?:\x1b\[[0-9;]*m)*\s*Compiling(?:\x1b\[[0-9;]*m)*\s+(\S+)\s+v(\S+)")
+                .expect("Unable to parse regex...")
+        });
+
+        let captures = COMPILING_REGEX.captures(line)?;
+
+        Some(BuildEvent::Compiling {
+            package: captures[1].to_string(),
+            version: captures[2].to_string(),
+        })
+    }
+}
+
+#[test]
+fn should_parse_status_lines() {
+    assert_eq!(
+        BuildEvent::from_status_line("   Compiling sample-ptx-crate v0.1.0 (/tmp/sample-crate)"),
+        Some(BuildEvent::Compiling {
+            package: String::from("sample-ptx-crate"),
+            version: String::from("0.1.0"),
+        })
+    );
+
+    assert_eq!(
+        BuildEvent::from_status_line(
+            "\x1b[1m\x1b[32m   Compiling\x1b[0m sample-ptx-crate v0.1.0 (/tmp/sample-crate)"
+        ),
+        Some(BuildEvent::Compiling {
+            package: String::from("sample-ptx-crate"),
+            version: String::from("0.1.0"),
+        })
+    );
+
+    assert_eq!(
+        BuildEvent::from_status_line("     Running `rustc --crate-name sample_ptx_crate`"),
+        None
+    );
+}
+
+#[test]
+fn should_parse_json_messages() {
+    assert_eq!(
+        BuildEvent::from_json_message(
+            r#"{"reason":"compiler-message","package_id":"sample","message":{"level":"warning","message":"unused variable: `x`","rendered":"warning: unused variable: `x`\n"}}"#
+        ),
+        [BuildEvent::Diagnostic {
+            level: String::from("warning"),
+            message: String::from("unused variable: `x`"),
+            rendered: Some(String::from("warning: unused variable: `x`\n")),
+        }]
+    );
+
+    assert_eq!(
+        BuildEvent::from_json_message(
+            r#"{"reason":"compiler-artifact","package_id":"sample","filenames":["/tmp/a.ptx","/tmp/liba.rlib"]}"#
+        ),
+        [
+            BuildEvent::ArtifactReady {
+                package_id: String::from("sample"),
+                path: PathBuf::from("/tmp/a.ptx"),
+            },
+            BuildEvent::ArtifactReady {
+                package_id: String::from("sample"),
+                path: PathBuf::from("/tmp/liba.rlib"),
+            },
+        ]
+    );
+
+    assert_eq!(
+        BuildEvent::from_json_message(
+            r#"{"reason":"build-script-executed","package_id":"sample","out_dir":"/tmp/out"}"#
+        ),
+        [BuildEvent::BuildScriptExecuted {
+            package_id: String::from("sample"),
+            out_dir: PathBuf::from("/tmp/out"),
+        }]
+    );
+
+    assert_eq!(
+        BuildEvent::from_json_message(r#"{"reason":"build-finished","success":true}"#),
+        []
+    );
+    assert_eq!(BuildEvent::from_json_message("not a message"), []);
+}
@@ -1,17 +1,29 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
 use semver::{Version, VersionReq};
 
 use crate::error::Result;
 
 mod process;
 pub mod runner;
+mod version;
 #[allow(clippy::module_name_repetitions)]
-pub use self::runner::{ExecutableRunner, Output};
+pub use self::runner::{CancellationToken, ExecutableRunner, Output};
 
 /// Details and requirements for executables.
 pub trait Executable {
     /// Returns executable name in `PATH`.
     fn get_name(&self) -> String;
 
+    /// Returns a command which invokes the executable.
+    fn get_command(&self) -> Command {
+        Command::new(self.get_name())
+    }
+
     /// Returns message about how to install missing executable.
     fn get_verification_hint(&self) -> String;
 
@@ -21,6 +33,41 @@ pub trait Executable {
     /// Executable version constraint.
     fn get_required_version(&self) -> Option<VersionReq>;
 
+    /// Returns the absolute path of the executable, which the command
+    /// invokes, or `None` if it cannot be found.
+    ///
+    /// Symlinks are resolved as well, so that the version check is repeated
+    /// once the executable has been updated.
+    fn resolve_path(&self) -> Option<PathBuf> {
+        let command = self.get_command();
+        let program = Path::new(command.get_program());
+
+        if program.components().count() > 1 {
+            let program = match command.get_current_dir() {
+                Some(cwd) => cwd.join(program),
+                None => program.to_path_buf(),
+            };
+
+            return program.canonicalize().ok();
+        }
+
+        let path = command
+            .get_envs()
+            .find(|(key, _)| *key == "PATH")
+            .map_or_else(|| env::var_os("PATH"), |(_, value)| value.map(Into::into))?;
+
+        env::split_paths(&path)
+            .flat_map(|dir| {
+                let mut executable = dir.join(program).into_os_string();
+                executable.push(env::consts::EXE_SUFFIX);
+
+                [dir.join(program), PathBuf::from(executable)]
+            })
+            .find(|candidate| candidate.is_file())?
+            .canonicalize()
+            .ok()
+    }
+
     /// Returns the current version of the executable.
     fn get_current_version(&self) -> Result<Version>
     where
@@ -30,14 +77,128 @@ pub trait Executable {
     }
 }
 
+/// Rust toolchain which `cargo` and `rustc` are invoked from.
+///
+/// # Usage
+/// ``` no_run
+/// use ptx_builder::prelude::*;
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// Builder::new(".")?
+///     .with_toolchain("nightly-2024-05-01")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(PartialEq, Eq, Hash, Clone, Debug, Default)]
+pub enum Toolchain {
+    /// Whatever `cargo` and `rustc` are first in `PATH` (default).
+    ///
+    /// Inside a build script, this is usually the toolchain of the host crate.
+    #[default]
+    Ambient,
+
+    /// Named toolchain, which is selected through the rustup proxies, e.g.
+    /// `cargo +nightly-2024-05-01`.
+    Rustup(String),
+
+    /// Explicit paths to the `cargo` and `rustc` executables.
+    Path {
+        /// Path to the `cargo` executable.
+        cargo: PathBuf,
+        /// Path to the `rustc` executable, which is passed to `cargo` via
+        /// the `RUSTC` environment variable.
+        rustc: PathBuf,
+    },
+}
+
+impl From<&str> for Toolchain {
+    fn from(name: &str) -> Self {
+        Toolchain::Rustup(String::from(name))
+    }
+}
+
+impl From<String> for Toolchain {
+    fn from(name: String) -> Self {
+        Toolchain::Rustup(name)
+    }
+}
+
 /// `cargo` command.
+///
+/// It is invoked from the ambient toolchain, see [`CargoWith`] to select
+/// another one.
+#[derive(Clone, Copy, Debug, Default)]
 pub struct Cargo;
 
 impl Executable for Cargo {
+    fn get_name(&self) -> String {
+        CargoWith::default().get_name()
+    }
+
+    fn get_verification_hint(&self) -> String {
+        CargoWith::default().get_verification_hint()
+    }
+
+    fn get_version_hint(&self) -> String {
+        CargoWith::default().get_version_hint()
+    }
+
+    fn get_required_version(&self) -> Option<VersionReq> {
+        CargoWith::default().get_required_version()
+    }
+
+    fn get_current_version(&self) -> Result<Version> {
+        CargoWith::default().get_current_version()
+    }
+}
+
+/// `cargo` command of a specific toolchain.
+#[derive(Clone, Debug, Default)]
+pub struct CargoWith {
+    toolchain: Toolchain,
+}
+
+impl CargoWith {
+    /// Creates `cargo` command from the specified toolchain.
+    #[must_use]
+    pub fn new(toolchain: Toolchain) -> Self {
+        CargoWith { toolchain }
+    }
+}
+
+impl Executable for CargoWith {
     fn get_name(&self) -> String {
         String::from("cargo")
     }
 
+    fn get_command(&self) -> Command {
+        match &self.toolchain {
+            Toolchain::Ambient => Command::new(self.get_name()),
+
+            Toolchain::Rustup(name) => {
+                let mut command = Command::new(self.get_name());
+
+                // `RUSTC` is set for build scripts and would take precedence
+                // over the `rustc` of the selected toolchain
+                command
+                    .arg(format!("+{name}"))
+                    .env_remove("RUSTC")
+                    .env_remove("RUSTDOC");
+
+                command
+            }
+
+            Toolchain::Path { cargo, rustc } => {
+                let mut command = Command::new(cargo);
+
+                command.env("RUSTC", rustc);
+                command
+            }
+        }
+    }
+
     fn get_verification_hint(&self) -> String {
         String::from("Please make sure you have it installed and in PATH")
     }
@@ -60,3 +221,144 @@ impl Executable for Cargo {
         })
     }
 }
+
+/// `rustc` command.
+#[derive(Clone, Debug, Default)]
+pub struct Rustc {
+    toolchain: Toolchain,
+}
+
+impl Rustc {
+    /// Creates `rustc` command from the specified toolchain.
+    #[must_use]
+    pub fn new(toolchain: Toolchain) -> Self {
+        Rustc { toolchain }
+    }
+}
+
+impl Executable for Rustc {
+    fn get_name(&self) -> String {
+        String::from("rustc")
+    }
+
+    fn get_command(&self) -> Command {
+        match &self.toolchain {
+            Toolchain::Ambient => Command::new(self.get_name()),
+
+            Toolchain::Rustup(name) => {
+                let mut command = Command::new(self.get_name());
+
+                command.arg(format!("+{name}"));
+                command
+            }
+
+            Toolchain::Path { rustc, .. } => Command::new(rustc),
+        }
+    }
+
+    fn get_verification_hint(&self) -> String {
+        String::from("Please make sure you have it installed and in PATH")
+    }
+
+    fn get_version_hint(&self) -> String {
+        String::from("Please update Rust to the latest nightly version")
+    }
+
+    fn get_required_version(&self) -> Option<VersionReq> {
+        None
+    }
+}
+
+/// `ptxas` command of the CUDA toolkit.
+#[derive(Clone, Debug)]
+pub struct Ptxas {
+    path: PathBuf,
+}
+
+impl Default for Ptxas {
+    fn default() -> Self {
+        Ptxas::new("ptxas")
+    }
+}
+
+impl Ptxas {
+    /// Creates `ptxas` command from the specified path, e.g.
+    /// `/usr/local/cuda/bin/ptxas`.
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Ptxas { path: path.into() }
+    }
+}
+
+impl Executable for Ptxas {
+    fn get_name(&self) -> String {
+        String::from("ptxas")
+    }
+
+    fn get_command(&self) -> Command {
+        Command::new(&self.path)
+    }
+
+    fn get_verification_hint(&self) -> String {
+        String::from("Please make sure you have the CUDA toolkit installed and `ptxas` in PATH")
+    }
+
+    fn get_version_hint(&self) -> String {
+        String::from("Please update the CUDA toolkit")
+    }
+
+    fn get_required_version(&self) -> Option<VersionReq> {
+        None
+    }
+
+    fn get_current_version(&self) -> Result<Version> {
+        self::runner::parse_cuda_version(self)
+    }
+}
+
+/// `fatbinary` command of the CUDA toolkit.
+#[derive(Clone, Debug)]
+pub struct Fatbinary {
+    path: PathBuf,
+}
+
+impl Default for Fatbinary {
+    fn default() -> Self {
+        Fatbinary::new("fatbinary")
+    }
+}
+
+impl Fatbinary {
+    /// Creates `fatbinary` command from the specified path, e.g.
+    /// `/usr/local/cuda/bin/fatbinary`.
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Fatbinary { path: path.into() }
+    }
+}
+
+impl Executable for Fatbinary {
+    fn get_name(&self) -> String {
+        String::from("fatbinary")
+    }
+
+    fn get_command(&self) -> Command {
+        Command::new(&self.path)
+    }
+
+    fn get_verification_hint(&self) -> String {
+        String::from("Please make sure you have the CUDA toolkit installed and `fatbinary` in PATH")
+    }
+
+    fn get_version_hint(&self) -> String {
+        String::from("Please update the CUDA toolkit")
+    }
+
+    fn get_required_version(&self) -> Option<VersionReq> {
+        None
+    }
+
+    fn get_current_version(&self) -> Result<Version> {
+        self::runner::parse_cuda_version(self)
+    }
+}
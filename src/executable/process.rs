@@ -6,14 +6,35 @@
 
 use std::{
     io,
-    process::{Command, Output, Stdio},
+    process::{Child, Command, Output, Stdio},
+    time::Duration,
 };
 
+/// How often the interruption condition is checked while the child is quiet.
+pub const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Result of a streamed command.
+pub enum Completion {
+    /// The command exited on its own.
+    Finished(Output),
+
+    /// The command was killed, together with its descendants, because
+    /// `should_stop` returned `true`. Contains the output captured so far.
+    Interrupted(Output),
+}
+
+/// Runs `cmd` and streams its output line by line.
+///
+/// When `should_stop` is given, the command is spawned in its own process
+/// group, so that the whole process tree can be killed once it returns
+/// `true`. Otherwise, the command stays in the caller's group and receives
+/// its terminal signals, like `Ctrl+C`.
 pub fn streaming_output<O: FnMut(&str), E: FnMut(&str)>(
     cmd: &mut Command,
     mut on_stdout_line: O,
     mut on_stderr_line: E,
-) -> io::Result<Output> {
+    mut should_stop: Option<&mut dyn FnMut() -> bool>,
+) -> io::Result<Completion> {
     let mut stdout = Vec::new();
     let mut stderr = Vec::new();
 
@@ -22,11 +43,21 @@ pub fn streaming_output<O: FnMut(&str), E: FnMut(&str)>(
         .stderr(Stdio::piped())
         .stdin(Stdio::null());
 
-    let status = {
+    #[cfg(unix)]
+    if should_stop.is_some() {
+        std::os::unix::process::CommandExt::process_group(cmd, 0);
+    }
+
+    let (status, interrupted) = {
         let mut child = cmd.spawn()?;
         let out = child.stdout.take().unwrap();
         let err = child.stderr.take().unwrap();
-        imp::read2(out, err, &mut |is_out, data, eof| {
+        let mut should_stop = || {
+            should_stop
+                .as_mut()
+                .is_some_and(|should_stop| should_stop())
+        };
+        let interrupted = imp::read2(out, err, &mut should_stop, &mut |is_out, data, eof| {
             let idx = if eof {
                 data.len()
             } else {
@@ -53,16 +84,73 @@ pub fn streaming_output<O: FnMut(&str), E: FnMut(&str)>(
                 }
             }
         })?;
-        child.wait()?
+
+        if interrupted {
+            kill_tree(&mut child)?;
+        }
+
+        (child.wait()?, interrupted)
     };
 
-    Ok(Output {
+    let output = Output {
         status,
         stdout,
         stderr,
+    };
+
+    Ok(if interrupted {
+        Completion::Interrupted(output)
+    } else {
+        Completion::Finished(output)
     })
 }
 
+#[cfg(unix)]
+fn kill_tree(child: &mut Child) -> io::Result<()> {
+    kill_process_tree(child.id())
+}
+
+/// Kills every process in the group led by `leader`.
+///
+/// The command must have been spawned as the leader of its own process
+/// group, see `streaming_output`.
+#[cfg(unix)]
+pub fn kill_process_tree(leader: u32) -> io::Result<()> {
+    let pgid = libc::pid_t::try_from(leader)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    if unsafe { libc::kill(-pgid, libc::SIGKILL) } == 0 {
+        return Ok(());
+    }
+
+    match io::Error::last_os_error() {
+        err if err.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+        err => Err(err),
+    }
+}
+
+#[cfg(windows)]
+fn kill_tree(child: &mut Child) -> io::Result<()> {
+    kill_process_tree(child.id()).or_else(|_| child.kill())
+}
+
+/// Kills `leader` together with all of its descendants.
+#[cfg(windows)]
+pub fn kill_process_tree(leader: u32) -> io::Result<()> {
+    let status = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &leader.to_string()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    // `taskkill` exits with 128 when the process has already exited
+    if status.success() || status.code() == Some(128) {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("`taskkill` failed with {status}")))
+    }
+}
+
 #[cfg(unix)]
 mod imp {
     use std::{
@@ -72,11 +160,15 @@ mod imp {
         process::{ChildStderr, ChildStdout},
     };
 
+    use super::POLL_INTERVAL;
+
+    /// Returns `true` if reading was interrupted by `should_stop`.
     pub(crate) fn read2(
         mut out_pipe: ChildStdout,
         mut err_pipe: ChildStderr,
+        should_stop: &mut dyn FnMut() -> bool,
         data: &mut dyn FnMut(bool, &mut Vec<u8>, bool),
-    ) -> io::Result<()> {
+    ) -> io::Result<bool> {
         unsafe {
             libc::fcntl(out_pipe.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK);
             libc::fcntl(err_pipe.as_raw_fd(), libc::F_SETFL, libc::O_NONBLOCK);
@@ -95,9 +187,19 @@ mod imp {
         let mut num_fds = 2;
         let mut errfd = 1;
 
+        #[allow(clippy::cast_possible_truncation)]
+        let timeout = POLL_INTERVAL.as_millis() as libc::c_int;
+
         while num_fds > 0 {
+            if should_stop() {
+                // hand out the partial lines captured so far
+                data(false, &mut err, true);
+                data(true, &mut out, true);
+                return Ok(true);
+            }
+
             // wait for either pipe to become readable using `select`
-            let r = unsafe { libc::poll(fds.as_mut_ptr(), num_fds, -1) };
+            let r = unsafe { libc::poll(fds.as_mut_ptr(), num_fds, timeout) };
             if r == -1 {
                 let err = io::Error::last_os_error();
                 if err.kind() == io::ErrorKind::Interrupted {
@@ -134,7 +236,7 @@ mod imp {
             }
             data(true, &mut out, out_done);
         }
-        Ok(())
+        Ok(false)
     }
 }
 
@@ -152,7 +254,9 @@ mod imp {
         pipe::NamedPipe,
         Overlapped,
     };
-    use winapi::shared::winerror::ERROR_BROKEN_PIPE;
+    use winapi::shared::winerror::{ERROR_BROKEN_PIPE, WAIT_TIMEOUT};
+
+    use super::POLL_INTERVAL;
 
     struct Pipe<'a> {
         dst: &'a mut Vec<u8>,
@@ -161,11 +265,13 @@ mod imp {
         done: bool,
     }
 
+    /// Returns `true` if reading was interrupted by `should_stop`.
     pub(crate) fn read2(
         out_pipe: ChildStdout,
         err_pipe: ChildStderr,
+        should_stop: &mut dyn FnMut() -> bool,
         data: &mut dyn FnMut(bool, &mut Vec<u8>, bool),
-    ) -> io::Result<()> {
+    ) -> io::Result<bool> {
         let mut out = Vec::new();
         let mut err = Vec::new();
 
@@ -183,7 +289,20 @@ mod imp {
             let mut status = [CompletionStatus::zero(), CompletionStatus::zero()];
 
             while !out_pipe.done || !err_pipe.done {
-                for status in port.get_many(&mut status, None)? {
+                if should_stop() {
+                    // hand out the partial lines captured so far
+                    data(false, err_pipe.dst, true);
+                    data(true, out_pipe.dst, true);
+                    return Ok(true);
+                }
+
+                let statuses = match port.get_many(&mut status, Some(POLL_INTERVAL)) {
+                    Ok(statuses) => statuses,
+                    Err(e) if e.raw_os_error() == Some(WAIT_TIMEOUT as i32) => continue,
+                    Err(e) => return Err(e),
+                };
+
+                for status in statuses {
                     if status.token() == 0 {
                         out_pipe.complete(status);
                         data(true, out_pipe.dst, out_pipe.done);
@@ -196,7 +315,7 @@ mod imp {
                 }
             }
 
-            Ok(())
+            Ok(false)
         }
     }
 
@@ -1,16 +1,84 @@
-use std::{ffi::OsStr, path::Path, process::Command};
+use std::{
+    env,
+    ffi::OsStr,
+    io,
+    path::Path,
+    process::Command,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use regex::Regex;
 use semver::Version;
 
-use crate::error::{BuildErrorKind, Error, Result, ResultExt};
+use crate::{
+    environment::EnvPolicy,
+    error::{BuildErrorKind, Error, Result, ResultExt},
+};
 
-use super::{process::streaming_output, Executable};
+use super::{
+    process::{streaming_output, Completion},
+    Executable,
+};
+
+#[cfg(feature = "async")]
+use super::process::POLL_INTERVAL;
 
 #[allow(clippy::module_name_repetitions)]
 pub struct ExecutableRunner<Ex: Executable> {
     command: Command,
     executable: Ex,
+    timeout: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+}
+
+/// Handle to abort a running build from another thread.
+///
+/// Cancelling kills the `cargo` process together with everything it has
+/// spawned, and the build fails with [`BuildErrorKind::Cancelled`].
+///
+/// # Usage
+/// ``` no_run
+/// use std::thread;
+///
+/// use ptx_builder::prelude::*;
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// let token = CancellationToken::new();
+/// let builder = Builder::new(".")?.with_cancellation(token.clone());
+///
+/// thread::spawn(move || token.cancel());
+///
+/// builder.build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Creates a token, which is not cancelled yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests all the builds which use this token to be aborted.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once [`CancellationToken::cancel`] has been called.
+    #[must_use]
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
 }
 
 #[derive(Debug)]
@@ -22,8 +90,10 @@ pub struct Output {
 impl<Ex: Executable> ExecutableRunner<Ex> {
     pub fn new(executable: Ex) -> Self {
         ExecutableRunner {
-            command: Command::new(executable.get_name()),
+            command: executable.get_command(),
             executable,
+            timeout: None,
+            cancellation: None,
         }
     }
 
@@ -45,6 +115,39 @@ impl<Ex: Executable> ExecutableRunner<Ex> {
         self
     }
 
+    /// Strips the inherited environment variables which are denied by the
+    /// `policy`, unless they have been explicitly set for the command.
+    pub fn with_env_policy(&mut self, policy: &EnvPolicy) -> &mut Self {
+        for (key, _) in env::vars_os() {
+            let is_explicit = self
+                .command
+                .get_envs()
+                .any(|(explicit, val)| explicit == key && val.is_some());
+
+            if policy.is_denied(&key) && !is_explicit {
+                self.command.env_remove(key);
+            }
+        }
+
+        self
+    }
+
+    /// Kills the command if it runs longer than `timeout`.
+    ///
+    /// Only applies to [`ExecutableRunner::run_live`].
+    pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Kills the command once the `token` is cancelled.
+    ///
+    /// Only applies to [`ExecutableRunner::run_live`].
+    pub fn with_cancellation(&mut self, token: &CancellationToken) -> &mut Self {
+        self.cancellation = Some(token.clone());
+        self
+    }
+
     pub fn with_cwd<P>(&mut self, path: P) -> &mut Self
     where
         P: AsRef<Path>,
@@ -56,14 +159,7 @@ impl<Ex: Executable> ExecutableRunner<Ex> {
     pub fn run(&mut self) -> Result<Output> {
         self.check_version()?;
 
-        let raw_output = {
-            self.command.output().with_context(|| {
-                BuildErrorKind::InternalError(format!(
-                    "Unable to execute command '{}'",
-                    self.executable.get_name()
-                ))
-            })?
-        };
+        let raw_output = started(self.command.output(), &self.executable)?;
 
         let output = Output {
             stdout: String::from_utf8(raw_output.stdout).context(BuildErrorKind::OtherError)?,
@@ -88,13 +184,46 @@ impl<Ex: Executable> ExecutableRunner<Ex> {
     ) -> Result<Output> {
         self.check_version()?;
 
-        let raw_output = streaming_output(&mut self.command, on_stdout_line, on_stderr_line)
-            .with_context(|| {
-                BuildErrorKind::InternalError(format!(
-                    "Unable to execute command '{}'",
-                    self.executable.get_name()
-                ))
-            })?;
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let cancellation = self.cancellation.clone();
+        let mut timed_out = false;
+
+        let mut should_stop = || {
+            timed_out = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            timed_out
+                || cancellation
+                    .as_ref()
+                    .is_some_and(CancellationToken::is_cancelled)
+        };
+
+        let interruptible = deadline.is_some() || self.cancellation.is_some();
+
+        let completion = started(
+            streaming_output(
+                &mut self.command,
+                on_stdout_line,
+                on_stderr_line,
+                interruptible.then_some(&mut should_stop as &mut dyn FnMut() -> bool),
+            ),
+            &self.executable,
+        )?;
+
+        let raw_output = match completion {
+            Completion::Finished(raw_output) => raw_output,
+
+            // the output might have been cut in the middle of a character
+            Completion::Interrupted(raw_output) => {
+                let stdout = String::from_utf8_lossy(&raw_output.stdout).into_owned();
+                let stderr = String::from_utf8_lossy(&raw_output.stderr).into_owned();
+
+                bail!(interruption(
+                    self.executable.get_name(),
+                    self.timeout.filter(|_| timed_out),
+                    stdout,
+                    stderr,
+                ));
+            }
+        };
 
         let output = Output {
             stdout: String::from_utf8(raw_output.stdout).context(BuildErrorKind::OtherError)?,
@@ -112,27 +241,287 @@ impl<Ex: Executable> ExecutableRunner<Ex> {
         }
     }
 
+    /// Like [`ExecutableRunner::run_live`], but runs the command with
+    /// `tokio::process`, and the version check on the blocking thread pool.
+    ///
+    /// The command is killed together with everything it has spawned when
+    /// the returned future is dropped.
+    #[cfg(feature = "async")]
+    pub async fn run_async<O: FnMut(&str), E: FnMut(&str)>(
+        self,
+        mut on_stdout_line: O,
+        mut on_stderr_line: E,
+    ) -> Result<Output>
+    where
+        Ex: Send + 'static,
+    {
+        use std::process::Stdio;
+
+        use tokio::io::{AsyncBufReadExt, BufReader};
+
+        let runner = unblock(move || self.check_version().map(|()| self)).await?;
+        let name = runner.executable.get_name();
+        let mut command = tokio::process::Command::from(runner.command);
+
+        // dropping the future interrupts the command too, so unlike
+        // `streaming_output` every command gets its own process group
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let mut child = started(
+            command
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .stdin(Stdio::null())
+                .kill_on_drop(true)
+                .spawn(),
+            &runner.executable,
+        )?;
+
+        let mut tree = ProcessTree(child.id());
+
+        let (Some(child_stdout), Some(child_stderr)) = (child.stdout.take(), child.stderr.take())
+        else {
+            bail!(BuildErrorKind::InternalError(format!(
+                "Unable to capture the output of command '{name}'"
+            )));
+        };
+
+        let mut stdout_lines = BufReader::new(child_stdout).lines();
+        let mut stderr_lines = BufReader::new(child_stderr).lines();
+
+        let (mut stdout, mut stderr) = (String::new(), String::new());
+        let (mut stdout_done, mut stderr_done) = (false, false);
+
+        let deadline = runner
+            .timeout
+            .map(|timeout| tokio::time::Instant::now() + timeout);
+        let mut timed_out = None;
+
+        while !stdout_done || !stderr_done {
+            tokio::select! {
+                line = stdout_lines.next_line(), if !stdout_done => {
+                    match line.context(BuildErrorKind::OtherError)? {
+                        Some(line) => {
+                            on_stdout_line(&line);
+                            stdout.push_str(&line);
+                            stdout.push('\n');
+                        }
+
+                        None => stdout_done = true,
+                    }
+                }
+
+                line = stderr_lines.next_line(), if !stderr_done => {
+                    match line.context(BuildErrorKind::OtherError)? {
+                        Some(line) => {
+                            on_stderr_line(&line);
+                            stderr.push_str(&line);
+                            stderr.push('\n');
+                        }
+
+                        None => stderr_done = true,
+                    }
+                }
+
+                () = sleep_until(deadline) => {
+                    timed_out = Some(true);
+                    break;
+                }
+
+                () = cancelled(runner.cancellation.as_ref()) => {
+                    timed_out = Some(false);
+                    break;
+                }
+            }
+        }
+
+        if let Some(timed_out) = timed_out {
+            tree.kill().context(BuildErrorKind::OtherError)?;
+            child.wait().await.context(BuildErrorKind::OtherError)?;
+
+            bail!(interruption(
+                name,
+                runner.timeout.filter(|_| timed_out),
+                stdout,
+                stderr
+            ));
+        }
+
+        let status = child.wait().await.with_context(|| {
+            BuildErrorKind::InternalError(format!("Unable to execute command '{name}'"))
+        })?;
+
+        // the process id might be reused once the command has been reaped
+        tree.0 = None;
+
+        if status.success() {
+            Ok(Output { stdout, stderr })
+        } else {
+            Err(Error::from(BuildErrorKind::CommandFailed {
+                command: name,
+                code: status.code().unwrap_or(-1),
+                stderr,
+            }))
+        }
+    }
+
     fn check_version(&self) -> Result<()> {
-        let current = self.executable.get_current_version()?;
-        let required = self.executable.get_required_version();
-
-        match required {
-            Some(ref required) if !required.matches(&current) => {
-                Err(Error::from(BuildErrorKind::CommandVersionNotFulfilled {
-                    command: self.executable.get_name(),
-                    current,
-                    required: required.clone(),
-                    hint: self.executable.get_version_hint(),
-                }))
+        // the version is only queried to be checked
+        let Some(required) = self.executable.get_required_version() else {
+            return Ok(());
+        };
+
+        let current = super::version::current_version(&self.executable)?;
+
+        if required.matches(&current) {
+            Ok(())
+        } else {
+            Err(Error::from(BuildErrorKind::CommandVersionNotFulfilled {
+                command: self.executable.get_name(),
+                current,
+                required,
+                hint: self.executable.get_version_hint(),
+            }))
+        }
+    }
+}
+
+/// Adds the context of a command, which could not be started, e.g. because
+/// it is not installed.
+fn started<T, Ex: Executable>(result: io::Result<T>, executable: &Ex) -> Result<T> {
+    let not_found = result
+        .as_ref()
+        .is_err_and(|error| error.kind() == io::ErrorKind::NotFound);
+
+    result.with_context(|| {
+        if not_found {
+            BuildErrorKind::CommandNotFound {
+                command: executable.get_name(),
+                hint: executable.get_verification_hint(),
             }
+        } else {
+            BuildErrorKind::InternalError(format!(
+                "Unable to execute command '{}'",
+                executable.get_name()
+            ))
+        }
+    })
+}
+
+/// Returns the error of a killed command, which has timed out after `timeout`
+/// or has been cancelled otherwise.
+fn interruption(
+    command: String,
+    timeout: Option<Duration>,
+    stdout: String,
+    stderr: String,
+) -> BuildErrorKind {
+    match timeout {
+        Some(timeout) => BuildErrorKind::TimedOut {
+            command,
+            timeout,
+            stdout,
+            stderr,
+        },
 
-            _ => Ok(()),
+        None => BuildErrorKind::Cancelled {
+            command,
+            stdout,
+            stderr,
+        },
+    }
+}
+
+/// Runs the blocking `f` on the thread pool of the async runtime.
+#[cfg(feature = "async")]
+pub(crate) async fn unblock<T, F>(f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .context(BuildErrorKind::OtherError)?
+}
+
+/// Process tree of an async command, which is killed on drop unless the
+/// command has been reaped.
+#[cfg(feature = "async")]
+struct ProcessTree(Option<u32>);
+
+#[cfg(feature = "async")]
+impl ProcessTree {
+    fn kill(&mut self) -> std::io::Result<()> {
+        match self.0.take() {
+            Some(pid) => super::process::kill_process_tree(pid),
+            None => Ok(()),
         }
     }
 }
 
+#[cfg(feature = "async")]
+impl Drop for ProcessTree {
+    fn drop(&mut self) {
+        // `kill_on_drop` would only kill the command itself
+        let _ = self.kill();
+    }
+}
+
+#[cfg(feature = "async")]
+async fn sleep_until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(feature = "async")]
+async fn cancelled(token: Option<&CancellationToken>) {
+    let Some(token) = token else {
+        return std::future::pending().await;
+    };
+
+    while !token.is_cancelled() {
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
 pub(crate) fn parse_executable_version<E: Executable>(executable: &E) -> Result<Version> {
-    let mut command = Command::new(executable.get_name());
+    let output = version_output(executable)?;
+
+    let version_regex = Regex::new(&format!(r"{}\s(\S+)", executable.get_name()))
+        .context(BuildErrorKind::OtherError)?;
+
+    match version_regex.captures(&output) {
+        Some(captures) => Ok(Version::parse(&captures[1]).context(BuildErrorKind::OtherError)?),
+
+        None => Err(Error::from(BuildErrorKind::InternalError(
+            "Unable to find executable version".into(),
+        ))),
+    }
+}
+
+/// Parses the version of a CUDA toolkit executable, which is only printed as
+/// part of the release, e.g. "Cuda compilation tools, release 12.4, V12.4.131".
+pub(crate) fn parse_cuda_version<E: Executable>(executable: &E) -> Result<Version> {
+    let output = version_output(executable)?;
+
+    let version_regex =
+        Regex::new(r"release \S+, V(\d+\.\d+\.\d+)").context(BuildErrorKind::OtherError)?;
+
+    match version_regex.captures(&output) {
+        Some(captures) => Ok(Version::parse(&captures[1]).context(BuildErrorKind::OtherError)?),
+
+        None => Err(Error::from(BuildErrorKind::InternalError(
+            "Unable to find executable version".into(),
+        ))),
+    }
+}
+
+/// Returns the combined output of the executable with `-V`.
+pub(crate) fn version_output<E: Executable>(executable: &E) -> Result<String> {
+    let mut command = executable.get_command();
 
     command.args(["-V"]);
 
@@ -158,14 +547,5 @@ pub(crate) fn parse_executable_version<E: Executable>(executable: &E) -> Result<
         });
     }
 
-    let version_regex = Regex::new(&format!(r"{}\s(\S+)", executable.get_name()))
-        .context(BuildErrorKind::OtherError)?;
-
-    match version_regex.captures(&(output.stdout + &output.stderr)) {
-        Some(captures) => Ok(Version::parse(&captures[1]).context(BuildErrorKind::OtherError)?),
-
-        None => Err(Error::from(BuildErrorKind::InternalError(
-            "Unable to find executable version".into(),
-        ))),
-    }
+    Ok(output.stdout + &output.stderr)
 }
@@ -0,0 +1,358 @@
+//! Process-wide and on-disk cache of executable versions, so that the
+//! version check does not spawn e.g. `cargo -V` before every command.
+
+use std::{
+    any::type_name,
+    collections::BTreeMap,
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::{Mutex, PoisonError},
+    time::UNIX_EPOCH,
+};
+
+use semver::Version;
+
+use crate::{environment::rustup_home, error::Result, fingerprint::Fingerprint, fresh::stamp};
+
+use super::Executable;
+
+static VERSIONS: Mutex<BTreeMap<String, Version>> = Mutex::new(BTreeMap::new());
+
+/// Key of a cached version, which is only stored on disk if it identifies
+/// the executable across processes.
+struct CacheKey {
+    name: String,
+    persistent: bool,
+}
+
+/// Returns the current version of the executable, which is only queried
+/// again once the executable has been replaced or updated.
+///
+/// Executables, which cannot be resolved to a file, are not cached. The
+/// versions of the rustup proxies are only cached within the process, unless
+/// the toolchain is selected explicitly, see `cache_key`.
+pub fn current_version<Ex: Executable>(executable: &Ex) -> Result<Version> {
+    let Some(key) = cache_key(executable) else {
+        return executable.get_current_version();
+    };
+
+    if let Some(version) = lock_versions().get(&key.name) {
+        return Ok(version.clone());
+    }
+
+    let path = key.persistent.then(|| cache_dir().join(&key.name));
+
+    let version = if let Some(version) = path.as_deref().and_then(load) {
+        version
+    } else {
+        let version = executable.get_current_version()?;
+
+        // the cache is only an optimisation, so a read-only or removed
+        // directory should not fail the build
+        if let Some(path) = &path {
+            let _ = store(path, &version);
+        }
+
+        version
+    };
+
+    lock_versions().insert(key.name, version.clone());
+
+    Ok(version)
+}
+
+fn lock_versions() -> std::sync::MutexGuard<'static, BTreeMap<String, Version>> {
+    VERSIONS.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The directory is shared by every crate, which is built with the same
+/// copy of the builder.
+fn cache_dir() -> PathBuf {
+    Path::new(env!("OUT_DIR")).join("executable-versions")
+}
+
+/// Identifies the executable by its resolved path, modification time and
+/// size, together with the arguments and environment of its command.
+///
+/// The rustup proxies pick the toolchain on every invocation, e.g. from the
+/// directory overrides or `rustup default`, so they are identified by the
+/// binary of an explicitly selected toolchain instead. Without one, the key
+/// is only valid within the process.
+///
+/// The type name is part of the key too, as it decides how the version is
+/// parsed, e.g. `Cargo` omits the release channel.
+fn cache_key<Ex: Executable>(executable: &Ex) -> Option<CacheKey> {
+    let path = executable.resolve_path()?;
+    let command = executable.get_command();
+
+    let mut fingerprint = Fingerprint::new();
+
+    fingerprint
+        .field("type", type_name::<Ex>())
+        .field("path", path.as_os_str().as_encoded_bytes());
+
+    for arg in command.get_args() {
+        fingerprint.field("arg", arg.as_encoded_bytes());
+    }
+
+    for (key, value) in command.get_envs() {
+        fingerprint.field("env", key.as_encoded_bytes()).field(
+            "value",
+            value.map_or(&[][..], |value| value.as_encoded_bytes()),
+        );
+    }
+
+    let is_proxy = is_rustup_proxy(&path);
+
+    let binary = if is_proxy {
+        let toolchain = selected_toolchain(&command);
+        fingerprint.field("rustup-toolchain", toolchain.as_encoded_bytes());
+
+        rustup_home().and_then(|rustup_home| {
+            find_toolchain_executable(&rustup_home, &toolchain, command.get_program())
+        })
+    } else {
+        Some(path)
+    };
+
+    let persistent = match binary
+        .as_deref()
+        .and_then(|binary| Some((binary, stamp(binary)?)))
+    {
+        Some((binary, stamp)) => {
+            let modified = stamp.modified.duration_since(UNIX_EPOCH).ok()?;
+
+            fingerprint
+                .field("binary", binary.as_os_str().as_encoded_bytes())
+                .field("modified", modified.as_nanos().to_le_bytes())
+                .field("len", stamp.len.to_le_bytes());
+
+            true
+        }
+
+        None if is_proxy => false,
+        None => return None,
+    };
+
+    Some(CacheKey {
+        name: format!("{}-{}", executable.get_name(), fingerprint.finish()),
+        persistent,
+    })
+}
+
+/// Returns `true` if the executable is a proxy of rustup, which picks the
+/// toolchain from e.g. the directory overrides and `rustup default`, so that
+/// neither its path nor its stamp change with the toolchain.
+///
+/// The proxies are either links to `rustup`, or its copies next to it.
+fn is_rustup_proxy(path: &Path) -> bool {
+    let rustup = format!("rustup{}", env::consts::EXE_SUFFIX);
+
+    path.file_name().is_some_and(|name| *name == *rustup) || path.with_file_name(rustup).is_file()
+}
+
+/// Returns the toolchain, which is selected for a proxy with e.g.
+/// `cargo +nightly` or `RUSTUP_TOOLCHAIN`, or an empty name if the proxy
+/// picks it on its own.
+fn selected_toolchain(command: &Command) -> OsString {
+    if let Some(name) = command
+        .get_args()
+        .next()
+        .and_then(OsStr::to_str)
+        .and_then(|arg| arg.strip_prefix('+'))
+    {
+        return OsString::from(name);
+    }
+
+    match command
+        .get_envs()
+        .find(|(key, _)| *key == "RUSTUP_TOOLCHAIN")
+    {
+        Some((_, value)) => value.map(OsString::from).unwrap_or_default(),
+        None => env::var_os("RUSTUP_TOOLCHAIN").unwrap_or_default(),
+    }
+}
+
+/// Returns the executable of the installed `toolchain`, which the proxy
+/// `program` runs, e.g. `~/.rustup/toolchains/nightly-{host}/bin/cargo`.
+fn find_toolchain_executable(
+    rustup_home: &Path,
+    toolchain: &OsStr,
+    program: &OsStr,
+) -> Option<PathBuf> {
+    if toolchain.is_empty() {
+        return None;
+    }
+
+    let mut executable = Path::new(program).file_stem()?.to_os_string();
+    executable.push(env::consts::EXE_SUFFIX);
+
+    // channels are installed for the host, while custom toolchains are
+    // linked with their plain names
+    let mut host_toolchain = toolchain.to_os_string();
+    host_toolchain.push("-");
+    host_toolchain.push(env!("PTX_BUILDER_HOST"));
+
+    let toolchains = rustup_home.join("toolchains");
+
+    [toolchains.join(toolchain), toolchains.join(host_toolchain)]
+        .into_iter()
+        .map(|dir| dir.join("bin").join(&executable))
+        .find(|path| path.is_file())
+}
+
+fn load(path: &Path) -> Option<Version> {
+    Version::parse(fs::read_to_string(path).ok()?.trim()).ok()
+}
+
+fn store(path: &Path, version: &Version) -> std::io::Result<()> {
+    let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+
+    fs::create_dir_all(cache_dir())?;
+    fs::write(&temp_path, version.to_string())?;
+    fs::rename(&temp_path, path)
+}
+
+#[cfg(test)]
+struct CountingCommand {
+    program: PathBuf,
+    nonce: String,
+    calls: std::cell::Cell<usize>,
+}
+
+#[cfg(test)]
+impl CountingCommand {
+    fn new<P: Into<PathBuf>>(program: P) -> Self {
+        CountingCommand {
+            program: program.into(),
+            nonce: format!(
+                "{}-{:?}",
+                std::process::id(),
+                std::time::SystemTime::now().duration_since(UNIX_EPOCH)
+            ),
+            calls: std::cell::Cell::new(0),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Executable for CountingCommand {
+    fn get_name(&self) -> String {
+        String::from("sh")
+    }
+
+    fn get_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(["-c", "echo sh 1.2.3", &self.nonce]);
+        command
+    }
+
+    fn get_verification_hint(&self) -> String {
+        String::new()
+    }
+
+    fn get_version_hint(&self) -> String {
+        String::new()
+    }
+
+    fn get_required_version(&self) -> Option<semver::VersionReq> {
+        None
+    }
+
+    fn get_current_version(&self) -> Result<Version> {
+        self.calls.set(self.calls.get() + 1);
+        super::runner::parse_executable_version(self)
+    }
+}
+
+#[test]
+fn should_cache_versions() {
+    let command = CountingCommand::new("sh");
+
+    assert_eq!(current_version(&command).unwrap(), Version::new(1, 2, 3));
+    assert_eq!(current_version(&command).unwrap(), Version::new(1, 2, 3));
+    assert_eq!(command.calls.get(), 1);
+
+    // a new process would only find the on-disk cache
+    let key = cache_key(&command).unwrap();
+    assert!(key.persistent);
+    lock_versions().remove(&key.name);
+
+    assert_eq!(current_version(&command).unwrap(), Version::new(1, 2, 3));
+    assert_eq!(command.calls.get(), 1);
+
+    let _ = fs::remove_file(cache_dir().join(key.name));
+}
+
+#[cfg(unix)]
+#[test]
+fn should_cache_rustup_proxies_within_process() {
+    let bin_dir = Path::new(env!("OUT_DIR")).join("rustup-proxy").join("bin");
+    let _ = fs::remove_dir_all(&bin_dir);
+    fs::create_dir_all(&bin_dir).unwrap();
+
+    // the stub prints the version of the toolchain set with `rustup default`
+    let rustup = bin_dir.join("rustup");
+    fs::write(
+        &rustup,
+        "#!/bin/sh\necho \"sh $(cat \"$(dirname \"$0\")/../default\")\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&rustup, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+    std::os::unix::fs::symlink(&rustup, bin_dir.join("sh")).unwrap();
+
+    let switch_toolchain = |version: &str| {
+        fs::write(bin_dir.parent().unwrap().join("default"), version).unwrap();
+    };
+
+    let command = CountingCommand::new(bin_dir.join("sh"));
+
+    switch_toolchain("1.79.0");
+    assert_eq!(current_version(&command).unwrap(), Version::new(1, 79, 0));
+    assert_eq!(current_version(&command).unwrap(), Version::new(1, 79, 0));
+    assert_eq!(command.calls.get(), 1);
+
+    // a new process has to ask the proxy again
+    let key = cache_key(&command).unwrap();
+    assert!(!key.persistent);
+    lock_versions().remove(&key.name);
+
+    switch_toolchain("1.80.0");
+    assert_eq!(current_version(&command).unwrap(), Version::new(1, 80, 0));
+    assert_eq!(command.calls.get(), 2);
+}
+
+#[test]
+fn should_find_toolchain_executables() {
+    let rustup_home = Path::new(env!("OUT_DIR")).join("rustup-home");
+    let host_toolchain = format!("nightly-{}", env!("PTX_BUILDER_HOST"));
+    let bin_dir = rustup_home
+        .join("toolchains")
+        .join(&host_toolchain)
+        .join("bin");
+    let cargo = bin_dir.join(format!("cargo{}", env::consts::EXE_SUFFIX));
+
+    let _ = fs::remove_dir_all(&rustup_home);
+    fs::create_dir_all(&bin_dir).unwrap();
+    fs::write(&cargo, "").unwrap();
+
+    let find = |toolchain: &str| {
+        find_toolchain_executable(&rustup_home, OsStr::new(toolchain), OsStr::new("cargo"))
+    };
+
+    assert_eq!(find("nightly"), Some(cargo.clone()));
+    assert_eq!(find(&host_toolchain), Some(cargo));
+    assert_eq!(find("stable"), None);
+    assert_eq!(find(""), None);
+
+    let mut command = Command::new("cargo");
+    command.arg("+nightly");
+    assert_eq!(selected_toolchain(&command), OsString::from("nightly"));
+
+    let mut command = Command::new("cargo");
+    command.env("RUSTUP_TOOLCHAIN", "stable").arg("build");
+    assert_eq!(selected_toolchain(&command), OsString::from("stable"));
+}
@@ -0,0 +1,179 @@
+use std::{
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    builder::{BuildOutput, GpuTarget},
+    error::{BuildErrorKind, Result, ResultExt},
+    executable::{ExecutableRunner, Fatbinary},
+};
+
+/// Fat binary, which packages cubins for several GPU architectures together
+/// with PTX assembly for forward compatibility.
+///
+/// The CUDA driver picks the cubin of the device's architecture, or JIT
+/// compiles the PTX assembly of the newest architecture the device supports.
+///
+/// # Usage in `build.rs`
+/// ```no_run
+/// use std::path::Path;
+///
+/// use ptx_builder::{fatbin::Fatbin, executable::{Fatbinary, Ptxas}, prelude::*};
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// let out_dir = std::env::var("OUT_DIR").unwrap();
+/// let mut fatbin = Fatbin::new(Fatbinary::default(), Path::new(&out_dir).join("kernels.fatbin"));
+///
+/// for arch in ["sm_75", "sm_86"] {
+///     let builder = Builder::new(".")?
+///         .set_target_cpu(arch)
+///         .with_ptxas(Ptxas::default(), arch);
+///
+///     if let BuildStatus::Success(output) = builder.build()? {
+///         fatbin = fatbin.with_output(&output)?;
+///     }
+/// }
+///
+/// println!("cargo:rustc-env=KERNEL_FATBIN_PATH={}", fatbin.create()?.display());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Fatbin {
+    fatbinary: Fatbinary,
+    path: PathBuf,
+    images: Vec<Image>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Image {
+    Cubin { arch: String, path: PathBuf },
+    Ptx { arch: String, path: PathBuf },
+}
+
+impl Fatbin {
+    /// Creates an empty fat binary, which `fatbinary` writes to `path`.
+    #[must_use]
+    pub fn new<P: AsRef<Path>>(fatbinary: Fatbinary, path: P) -> Self {
+        Fatbin {
+            fatbinary,
+            path: path.as_ref().to_path_buf(),
+            images: Vec::new(),
+        }
+    }
+
+    /// Adds the cubin at `path`, which was compiled for the GPU architecture
+    /// `arch` (e.g. `sm_75`).
+    #[must_use]
+    pub fn with_cubin<S: Into<String>, P: AsRef<Path>>(mut self, arch: S, path: P) -> Self {
+        self.images.push(Image::Cubin {
+            arch: arch.into(),
+            path: path.as_ref().to_path_buf(),
+        });
+        self
+    }
+
+    /// Adds the PTX assembly at `path`, which was generated for the GPU
+    /// architecture `arch` (e.g. `sm_75`).
+    #[must_use]
+    pub fn with_ptx<S: Into<String>, P: AsRef<Path>>(mut self, arch: S, path: P) -> Self {
+        self.images.push(Image::Ptx {
+            arch: arch.into(),
+            path: path.as_ref().to_path_buf(),
+        });
+        self
+    }
+
+    /// Adds the PTX assembly of a build, and its cubin if it was compiled
+    /// with [`Builder::with_ptxas`](crate::builder::Builder::with_ptxas).
+    ///
+    /// The architecture of the PTX assembly is read from its `.target`
+    /// directive.
+    pub fn with_output(mut self, output: &BuildOutput) -> Result<Self> {
+        let assembly_path = output.get_assembly_path();
+
+        if let (Some(arch), Some(cubin_path)) = (output.get_cubin_arch(), output.get_cubin_path()) {
+            self = self.with_cubin(arch, cubin_path);
+        }
+
+        let assembly = read_to_string(&assembly_path).context(BuildErrorKind::OtherError)?;
+
+        let arch = get_ptx_arch(&assembly)?;
+
+        Ok(self.with_ptx(arch, assembly_path))
+    }
+
+    /// Runs `fatbinary` and returns the path of the fat binary.
+    pub fn create(&self) -> Result<PathBuf> {
+        if self.images.is_empty() {
+            bail!(BuildErrorKind::InternalError(String::from(
+                "Unable to create a fat binary without any cubin or PTX assembly",
+            )));
+        }
+
+        let mut args = vec![
+            format!("--create={}", self.path.display()),
+            String::from("-64"),
+        ];
+
+        for image in &self.images {
+            let (profile, path) = match image {
+                Image::Cubin { arch, path } => {
+                    (format!("sm_{}", Self::get_arch_number(arch)?), path)
+                }
+                Image::Ptx { arch, path } => {
+                    (format!("compute_{}", Self::get_arch_number(arch)?), path)
+                }
+            };
+
+            args.push(format!("--image=profile={profile},file={}", path.display()));
+        }
+
+        ExecutableRunner::new(self.fatbinary.clone())
+            .with_args(args)
+            .run()?;
+
+        Ok(self.path.clone())
+    }
+
+    /// Returns the number of `sm_XX` or `compute_XX`, e.g. `"75"`.
+    fn get_arch_number(arch: &str) -> Result<&str> {
+        let number = arch
+            .strip_prefix("sm_")
+            .or_else(|| arch.strip_prefix("compute_"))
+            .filter(|number| number.starts_with(|digit: char| digit.is_ascii_digit()));
+
+        match number {
+            Some(number) => Ok(number),
+
+            None => Err(BuildErrorKind::InvalidTargetCpu {
+                target: String::from("fatbinary"),
+                target_cpu: Some(String::from(arch)),
+                hint: format!(
+                    "Please use a GPU architecture of `fatbinary`, e.g. `{}`",
+                    GpuTarget::Nvptx.get_example_target_cpu()
+                ),
+            }
+            .into()),
+        }
+    }
+}
+
+/// Returns the GPU architecture of the `.target` directive, e.g. `"sm_75"`.
+pub(crate) fn get_ptx_arch(assembly: &str) -> Result<&str> {
+    let arch = assembly
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(".target "))
+        .and_then(|targets| targets.split(',').next());
+
+    match arch {
+        Some(arch) => Ok(arch.trim()),
+
+        None => Err(BuildErrorKind::InternalError(String::from(
+            "Unable to find `.target` directive in the PTX assembly",
+        ))
+        .into()),
+    }
+}
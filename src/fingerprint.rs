@@ -0,0 +1,68 @@
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// Stable fingerprint of a build configuration.
+///
+/// Unlike `DefaultHasher`, the FNV-1a hash is fixed, so the same inputs
+/// produce the same fingerprint across Rust versions and platforms.
+#[derive(Clone, Debug)]
+pub struct Fingerprint {
+    state: u64,
+}
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Fingerprint {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+
+    /// Adds a named value, which is length-prefixed so that adjacent fields
+    /// cannot be confused with each other.
+    pub fn field<V: AsRef<[u8]>>(&mut self, name: &str, value: V) -> &mut Self {
+        let value = value.as_ref();
+
+        self.write(name.as_bytes());
+        self.write(&(value.len() as u64).to_le_bytes());
+        self.write(value);
+
+        self
+    }
+
+    /// Returns the fingerprint as a fixed-width hex string.
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.state)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= u64::from(*byte);
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+}
+
+#[test]
+fn should_be_stable() {
+    assert_eq!(Fingerprint::new().finish(), "cbf29ce484222325");
+
+    let mut fingerprint = Fingerprint::new();
+    fingerprint.write(b"a");
+
+    assert_eq!(fingerprint.finish(), "af63dc4c8601ec8c");
+}
+
+#[test]
+fn should_separate_fields() {
+    let first = Fingerprint::new()
+        .field("features", "ab")
+        .field("target-cpu", "c")
+        .finish();
+
+    let second = Fingerprint::new()
+        .field("features", "a")
+        .field("target-cpu", "bc")
+        .finish();
+
+    assert_ne!(first, second);
+}
@@ -0,0 +1,259 @@
+//! Record of a finished build, which allows to skip `cargo` entirely while
+//! none of the inputs have changed.
+
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::error::{BuildErrorKind, Result, ResultExt};
+
+/// Some filesystems only store modification times with a precision of up to
+/// two seconds.
+const MTIME_GRANULARITY: Duration = Duration::from_secs(2);
+
+/// Modification time and length of a file or directory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    pub modified: SystemTime,
+    pub len: u64,
+}
+
+/// Returns the current stamp of `path`, or `None` if it does not exist.
+pub fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some(Stamp {
+        modified: metadata.modified().ok()?,
+        len: metadata.len(),
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct FreshnessRecord {
+    pub output_path: PathBuf,
+    pub file_suffix: String,
+    stamps: Vec<(PathBuf, Option<Stamp>)>,
+}
+
+impl FreshnessRecord {
+    /// Stamps the `inputs` and `outputs` of a build, which has started at
+    /// `build_started`.
+    ///
+    /// Returns `None` if an input might have been modified during the build,
+    /// as it is unknown whether `rustc` has seen the modification.
+    pub fn new<I: IntoIterator<Item = PathBuf>, O: IntoIterator<Item = PathBuf>>(
+        output_path: PathBuf,
+        file_suffix: String,
+        inputs: I,
+        outputs: O,
+        build_started: SystemTime,
+    ) -> Option<Self> {
+        let stamp_all = |paths: Vec<PathBuf>| {
+            paths
+                .into_iter()
+                .map(|path| {
+                    let stamp = stamp(&path);
+                    (path, stamp)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut stamps = stamp_all(inputs.into_iter().collect());
+
+        let racy_since = build_started
+            .checked_sub(MTIME_GRANULARITY)
+            .unwrap_or(UNIX_EPOCH);
+
+        let is_racy = stamps
+            .iter()
+            .any(|(_, stamp)| stamp.is_some_and(|stamp| stamp.modified >= racy_since));
+
+        if is_racy {
+            return None;
+        }
+
+        stamps.extend(stamp_all(outputs.into_iter().collect()));
+
+        Some(FreshnessRecord {
+            output_path,
+            file_suffix,
+            stamps,
+        })
+    }
+
+    /// Returns bool indicating whether no input or output has changed since.
+    pub fn is_fresh(&self) -> bool {
+        self.stamps
+            .iter()
+            .all(|(path, recorded)| stamp(path) == *recorded)
+    }
+
+    /// Reads the record, if there is a valid one at `path`.
+    pub fn load(path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(path).ok()?;
+        let mut lines = contents.lines();
+
+        let output_path = PathBuf::from(lines.next()?.strip_prefix("output-path ")?);
+        let file_suffix = String::from(lines.next()?.strip_prefix("file-suffix ")?);
+
+        let stamps = lines
+            .map(|line| {
+                if let Some(path) = line.strip_prefix("missing ") {
+                    return Some((PathBuf::from(path), None));
+                }
+
+                let mut fields = line.strip_prefix("stamp ")?.splitn(4, ' ');
+
+                let secs = fields.next()?.parse().ok()?;
+                let nanos = fields.next()?.parse().ok()?;
+                let len = fields.next()?.parse().ok()?;
+                let path = PathBuf::from(fields.next()?);
+
+                let modified = UNIX_EPOCH.checked_add(Duration::new(secs, nanos))?;
+
+                Some((path, Some(Stamp { modified, len })))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(FreshnessRecord {
+            output_path,
+            file_suffix,
+            stamps,
+        })
+    }
+
+    /// Writes the record to `path`, replacing the previous one atomically.
+    ///
+    /// Paths which are not valid UTF-8 cannot be recorded, so the record is
+    /// removed instead.
+    pub fn store(&self, path: &Path) -> Result<()> {
+        let Some(contents) = self.serialize() else {
+            return Self::remove(path);
+        };
+
+        // builds for different compilers share the record, but not the lock
+        let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+
+        fs::write(&temp_path, contents).context(BuildErrorKind::OtherError)?;
+        fs::rename(&temp_path, path).context(BuildErrorKind::OtherError)?;
+
+        Ok(())
+    }
+
+    /// Removes the record at `path`, if there is any.
+    pub fn remove(path: &Path) -> Result<()> {
+        match fs::remove_file(path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => {
+                Err(error).context(BuildErrorKind::OtherError)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn serialize(&self) -> Option<String> {
+        let mut contents = format!(
+            "output-path {}\nfile-suffix {}\n",
+            self.output_path.to_str()?,
+            self.file_suffix
+        );
+
+        for (path, stamp) in &self.stamps {
+            let path = path.to_str()?;
+
+            match stamp {
+                Some(Stamp { modified, len }) => {
+                    let modified = modified.duration_since(UNIX_EPOCH).ok()?;
+
+                    writeln!(
+                        contents,
+                        "stamp {} {} {} {}",
+                        modified.as_secs(),
+                        modified.subsec_nanos(),
+                        len,
+                        path
+                    )
+                    .ok()?;
+                }
+
+                None => writeln!(contents, "missing {path}").ok()?,
+            }
+        }
+
+        Some(contents)
+    }
+}
+
+#[cfg(test)]
+fn record_test_dir(name: &str) -> PathBuf {
+    let path = Path::new(env!("OUT_DIR")).join("fresh-record").join(name);
+
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+
+    path
+}
+
+#[test]
+fn should_store_and_load_records() {
+    let dir = record_test_dir("roundtrip");
+    fs::write(dir.join("lib.rs"), "fn main() {}").unwrap();
+    fs::write(dir.join("lib.ptx"), "// assembly").unwrap();
+
+    let record = FreshnessRecord::new(
+        dir.join("output"),
+        String::from("-0123456789abcdef"),
+        [dir.join("lib.rs"), dir.join("missing.rs")],
+        [dir.join("lib.ptx")],
+        SystemTime::now() + Duration::from_secs(60),
+    )
+    .unwrap();
+
+    record.store(&dir.join("record")).unwrap();
+
+    let loaded = FreshnessRecord::load(&dir.join("record")).unwrap();
+
+    assert_eq!(loaded, record);
+    assert!(loaded.is_fresh());
+
+    fs::remove_file(dir.join("lib.ptx")).unwrap();
+    assert!(!loaded.is_fresh());
+}
+
+#[test]
+fn should_detect_changed_inputs() {
+    let dir = record_test_dir("changed");
+    fs::write(dir.join("lib.rs"), "fn main() {}").unwrap();
+
+    let record = FreshnessRecord::new(
+        dir.join("output"),
+        String::new(),
+        [dir.join("lib.rs")],
+        [],
+        SystemTime::now() + Duration::from_secs(60),
+    )
+    .unwrap();
+
+    fs::write(dir.join("lib.rs"), "fn main() { changed(); }").unwrap();
+    assert!(!record.is_fresh());
+}
+
+#[test]
+fn should_not_record_racy_inputs() {
+    let dir = record_test_dir("racy");
+    let build_started = SystemTime::now();
+
+    fs::write(dir.join("lib.rs"), "fn main() {}").unwrap();
+
+    let record = FreshnessRecord::new(
+        dir.join("output"),
+        String::new(),
+        [dir.join("lib.rs")],
+        [],
+        build_started,
+    );
+
+    assert_eq!(record, None);
+}
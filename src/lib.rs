@@ -24,15 +24,45 @@ pub mod executable;
 /// Build helpers.
 pub mod builder;
 
+/// Typed progress events of a build.
+pub mod event;
+
 /// Build reporting helpers.
 pub mod reporter;
 
+/// Packaging of device artifacts for several GPU architectures.
+pub mod fatbin;
+
+/// Embedding of PTX assembly in host crates.
+pub mod embed;
+
+/// Rewriting of the PTX assembly after a build.
+pub mod postprocess;
+
+/// Environment diagnostics.
+pub mod doctor;
+
+/// Environment of the device crate build.
+pub mod environment;
+
+/// Rebuilding on device source changes.
+pub mod watcher;
+
+mod fingerprint;
+mod fresh;
+mod lock;
+mod metadata;
 mod source;
 
 /// Convenient re-exports of mostly used types.
 pub mod prelude {
     pub use crate::{
-        builder::{BuildStatus, Builder, CrateType, MessageFormat, Profile},
+        builder::{BuildStatus, Builder, CrateType, GpuTarget, MessageFormat, Profile, Target},
+        event::BuildEvent,
+        executable::{CancellationToken, Ptxas, Toolchain},
         reporter::{CargoAdapter, ErrorLogPrinter},
     };
+
+    #[cfg(feature = "async")]
+    pub use crate::builder::{AsyncBuild, OutputLine};
 }
@@ -0,0 +1,228 @@
+//! Advisory cross-process lock of a device crate build directory.
+
+use std::{
+    fs::{File, OpenOptions},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::error::{BuildErrorKind, Result, ResultExt};
+
+const LOCK_FILE: &str = ".ptx-builder.lock";
+const MAX_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Exclusive lock of an output directory, released on drop.
+///
+/// `cargo` already serialises concurrent builds into the same target
+/// directory, but the files written by the builder itself (e.g. the cached
+/// build command) are only consistent while this lock is held.
+#[derive(Debug)]
+pub struct BuildLock {
+    file: File,
+}
+
+impl BuildLock {
+    /// Waits for up to `timeout` to lock the `output_path` directory.
+    pub fn acquire(output_path: &Path, timeout: Duration) -> Result<Self> {
+        let (path, file) = Self::open(output_path)?;
+        let mut retry = Retry::new(timeout);
+
+        loop {
+            if imp::try_lock(&file).context("Unable to lock the build directory")? {
+                return Ok(BuildLock { file });
+            }
+
+            thread::sleep(retry.next(&path)?);
+        }
+    }
+
+    /// Waits for up to `timeout` to lock the `output_path` directory without
+    /// blocking the async runtime.
+    #[cfg(feature = "async")]
+    pub async fn acquire_async(output_path: &Path, timeout: Duration) -> Result<Self> {
+        let (path, file) = Self::open(output_path)?;
+        let mut retry = Retry::new(timeout);
+
+        loop {
+            if imp::try_lock(&file).context("Unable to lock the build directory")? {
+                return Ok(BuildLock { file });
+            }
+
+            tokio::time::sleep(retry.next(&path)?).await;
+        }
+    }
+
+    fn open(output_path: &Path) -> Result<(PathBuf, File)> {
+        let path = output_path.join(LOCK_FILE);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .context("Unable to open the build directory lock")?;
+
+        Ok((path, file))
+    }
+}
+
+/// Exponential backoff between the attempts to lock.
+struct Retry {
+    started: Instant,
+    timeout: Duration,
+    interval: Duration,
+}
+
+impl Retry {
+    fn new(timeout: Duration) -> Self {
+        Retry {
+            started: Instant::now(),
+            timeout,
+            interval: Duration::from_millis(10),
+        }
+    }
+
+    /// Returns how long to wait before the next attempt, or fails if the
+    /// `timeout` ran out.
+    fn next(&mut self, path: &Path) -> Result<Duration> {
+        let elapsed = self.started.elapsed();
+
+        if elapsed >= self.timeout {
+            bail!(BuildErrorKind::LockTimeout {
+                path: path.to_path_buf(),
+                timeout: self.timeout,
+            });
+        }
+
+        let interval = self.interval.min(self.timeout.saturating_sub(elapsed));
+        self.interval = (self.interval * 2).min(MAX_RETRY_INTERVAL);
+
+        Ok(interval)
+    }
+}
+
+impl Drop for BuildLock {
+    fn drop(&mut self) {
+        // the lock is released with the file anyway, so errors are irrelevant
+        let _ = imp::unlock(&self.file);
+    }
+}
+
+#[cfg(unix)]
+mod imp {
+    use std::{fs::File, io, os::unix::prelude::*};
+
+    /// Returns `false` if the file is locked by somebody else.
+    pub(crate) fn try_lock(file: &File) -> io::Result<bool> {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(true);
+        }
+
+        let err = io::Error::last_os_error();
+
+        match err.kind() {
+            io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => Ok(false),
+            _ => Err(err),
+        }
+    }
+
+    pub(crate) fn unlock(file: &File) -> io::Result<()> {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) } == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{fs::File, io, mem, os::windows::prelude::*};
+
+    use windows_sys::Win32::{
+        Foundation::ERROR_LOCK_VIOLATION,
+        Storage::FileSystem::{
+            LockFileEx, UnlockFile, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY,
+        },
+        System::IO::OVERLAPPED,
+    };
+
+    /// Returns `false` if the file is locked by somebody else.
+    pub(crate) fn try_lock(file: &File) -> io::Result<bool> {
+        let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+
+        let locked = unsafe {
+            LockFileEx(
+                file.as_raw_handle(),
+                LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY,
+                0,
+                u32::MAX,
+                u32::MAX,
+                &mut overlapped,
+            )
+        };
+
+        if locked != 0 {
+            return Ok(true);
+        }
+
+        let err = io::Error::last_os_error();
+
+        match err.raw_os_error() {
+            Some(code) if code == ERROR_LOCK_VIOLATION as i32 => Ok(false),
+            _ => Err(err),
+        }
+    }
+
+    pub(crate) fn unlock(file: &File) -> io::Result<()> {
+        if unsafe { UnlockFile(file.as_raw_handle(), 0, 0, u32::MAX, u32::MAX) } != 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+mod imp {
+    use std::{fs::File, io};
+
+    pub(crate) fn try_lock(_file: &File) -> io::Result<bool> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "build directory locking is not supported on this platform",
+        ))
+    }
+
+    pub(crate) fn unlock(_file: &File) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(any(unix, windows))]
+#[test]
+fn should_time_out_while_locked() {
+    let output_path = Path::new(env!("OUT_DIR")).join("lock-test");
+    std::fs::create_dir_all(&output_path).unwrap();
+
+    let lock = BuildLock::acquire(&output_path, Duration::ZERO).unwrap();
+
+    // both `flock` and `LockFileEx` locks are bound to the open file, so a
+    // second open conflicts even within the same process
+    match BuildLock::acquire(&output_path, Duration::from_millis(50))
+        .unwrap_err()
+        .kind()
+    {
+        BuildErrorKind::LockTimeout { path, timeout } => {
+            assert_eq!(path, &output_path.join(LOCK_FILE));
+            assert_eq!(timeout, &Duration::from_millis(50));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+
+    drop(lock);
+
+    assert!(BuildLock::acquire(&output_path, Duration::ZERO).is_ok());
+}
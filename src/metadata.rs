@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    builder::{CrateType, Profile},
+    error::{BuildErrorKind, Result},
+};
+
+const METADATA_TABLE: &str = "[package.metadata.ptx-builder]";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Builder configuration declared in the device crate's `Cargo.toml`.
+pub struct Metadata {
+    pub profile: Option<Profile>,
+    pub crate_type: Option<CrateType>,
+    pub features: Option<Vec<String>>,
+    pub target_cpu: Option<String>,
+    pub env: BTreeMap<String, String>,
+    pub kernels: Option<Vec<String>>,
+}
+
+impl Metadata {
+    /// Extracts the `[package.metadata.ptx-builder]` table from a parsed
+    /// `Cargo.toml`, if there is any.
+    pub fn from_cargo_toml(cargo_toml: &toml::Value) -> Result<Self> {
+        match cargo_toml
+            .get("package")
+            .and_then(|package| package.get("metadata"))
+            .and_then(|metadata| metadata.get("ptx-builder"))
+        {
+            Some(table) => Self::parse(table),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn parse(table: &toml::Value) -> Result<Self> {
+        let Some(table) = table.as_table() else {
+            bail!(invalid(format!("{METADATA_TABLE} must be a table")));
+        };
+
+        let mut metadata = Self::default();
+
+        for (key, value) in table {
+            match key.as_str() {
+                "profile" => {
+                    metadata.profile = Some(match expect_str(key, value)? {
+                        "debug" => Profile::Debug,
+                        "release" => Profile::Release,
+                        other => bail!(invalid(format!(
+                            "unknown profile `{other}`, expected `debug` or `release`"
+                        ))),
+                    });
+                }
+
+                "crate-type" => {
+                    metadata.crate_type = Some(match expect_str(key, value)? {
+                        "lib" => CrateType::Library,
+                        "bin" => CrateType::Binary,
+                        other => bail!(invalid(format!(
+                            "unknown crate-type `{other}`, expected `lib` or `bin`"
+                        ))),
+                    });
+                }
+
+                "features" => metadata.features = Some(expect_str_array(key, value)?),
+                "target-cpu" => metadata.target_cpu = Some(expect_str(key, value)?.to_string()),
+                "kernels" => metadata.kernels = Some(expect_str_array(key, value)?),
+
+                "env" => {
+                    let Some(env) = value.as_table() else {
+                        bail!(invalid(format!("`{key}` must be a table of strings")));
+                    };
+
+                    for (name, value) in env {
+                        let Some(value) = value.as_str() else {
+                            bail!(invalid(format!("`{key}.{name}` must be a string")));
+                        };
+
+                        metadata.env.insert(name.clone(), value.to_string());
+                    }
+                }
+
+                other => bail!(invalid(format!("unknown key `{other}`"))),
+            }
+        }
+
+        Ok(metadata)
+    }
+}
+
+fn invalid(message: String) -> BuildErrorKind {
+    BuildErrorKind::InvalidMetadata(message)
+}
+
+fn expect_str<'a>(key: &str, value: &'a toml::Value) -> Result<&'a str> {
+    match value.as_str() {
+        Some(value) => Ok(value),
+        None => bail!(invalid(format!("`{key}` must be a string"))),
+    }
+}
+
+fn expect_str_array(key: &str, value: &toml::Value) -> Result<Vec<String>> {
+    let Some(items) = value.as_array() else {
+        bail!(invalid(format!("`{key}` must be an array of strings")));
+    };
+
+    items
+        .iter()
+        .map(|item| match item.as_str() {
+            Some(item) => Ok(item.to_string()),
+            None => bail!(invalid(format!("`{key}` must be an array of strings"))),
+        })
+        .collect()
+}
+
+#[test]
+fn should_parse_full_metadata() {
+    let cargo_toml: toml::Value = toml::from_str(
+        r#"
+        [package]
+        name = "configured"
+
+        [package.metadata.ptx-builder]
+        profile = "debug"
+        crate-type = "lib"
+        features = ["fast-math"]
+        target-cpu = "sm_75"
+        kernels = ["add", "mul"]
+        env = { SOME_VAR = "1" }
+        "#,
+    )
+    .unwrap();
+
+    let metadata = Metadata::from_cargo_toml(&cargo_toml).unwrap();
+
+    assert_eq!(metadata.profile, Some(Profile::Debug));
+    assert_eq!(metadata.crate_type, Some(CrateType::Library));
+    assert_eq!(metadata.features, Some(vec![String::from("fast-math")]));
+    assert_eq!(metadata.target_cpu.as_deref(), Some("sm_75"));
+    assert_eq!(
+        metadata.kernels,
+        Some(vec![String::from("add"), String::from("mul")])
+    );
+    assert_eq!(metadata.env.get("SOME_VAR").map(String::as_str), Some("1"));
+}
+
+#[test]
+fn should_default_without_metadata() {
+    let cargo_toml: toml::Value = toml::from_str(
+        r#"
+        [package]
+        name = "plain"
+
+        [package.metadata.other-tool]
+        key = "value"
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        Metadata::from_cargo_toml(&cargo_toml).unwrap(),
+        Metadata::default()
+    );
+}
+
+#[test]
+fn should_reject_unknown_keys() {
+    let cargo_toml: toml::Value = toml::from_str(
+        r#"
+        [package.metadata.ptx-builder]
+        target_cpu = "sm_75"
+        "#,
+    )
+    .unwrap();
+
+    match Metadata::from_cargo_toml(&cargo_toml).unwrap_err().kind() {
+        BuildErrorKind::InvalidMetadata(message) => {
+            assert_eq!(message, "unknown key `target_cpu`");
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_reject_invalid_values() {
+    let cargo_toml: toml::Value = toml::from_str(
+        r#"
+        [package.metadata.ptx-builder]
+        profile = "fast"
+        "#,
+    )
+    .unwrap();
+
+    match Metadata::from_cargo_toml(&cargo_toml).unwrap_err().kind() {
+        BuildErrorKind::InvalidMetadata(message) => {
+            assert_eq!(
+                message,
+                "unknown profile `fast`, expected `debug` or `release`"
+            );
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
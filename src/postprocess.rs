@@ -0,0 +1,285 @@
+use std::{fmt, sync::LazyLock};
+
+use regex::{Captures, Regex};
+
+use crate::error::Result;
+
+/// Rewriting step of the PTX assembly, see
+/// [`Builder::with_post_process`](crate::builder::Builder::with_post_process).
+///
+/// The steps operate on the text of the assembly, and are run in the order
+/// they were added after every successful build.
+///
+/// # Usage
+/// ```no_run
+/// use ptx_builder::{postprocess::PostProcess, prelude::*};
+/// # use ptx_builder::error::Result;
+///
+/// #[derive(Debug)]
+/// struct AppendNote;
+///
+/// impl PostProcess for AppendNote {
+///     fn process(&self, assembly: &str) -> Result<String> {
+///         Ok(format!("{assembly}// processed\n"))
+///     }
+/// }
+///
+/// # fn main() -> Result<()> {
+/// let builder = Builder::new(".")?.with_post_process(AppendNote);
+/// # Ok(())
+/// # }
+/// ```
+pub trait PostProcess: fmt::Debug + Send + Sync {
+    /// Returns the rewritten `assembly`.
+    fn process(&self, assembly: &str) -> Result<String>;
+}
+
+/// Removes `//` and `/* */` comments.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StripComments;
+
+/// Removes the `.debug_*` sections and the `.file` and `.loc` directives.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StripDebugInfo;
+
+/// Prepends a prefix to the names of all kernel entry points.
+#[derive(Clone, Debug)]
+pub struct PrefixEntryPoints {
+    prefix: String,
+}
+
+/// Replaces the PTX ISA version of the `.version` directive, e.g. with `8.0`.
+#[derive(Clone, Debug)]
+pub struct SetPtxVersion {
+    version: String,
+}
+
+impl PrefixEntryPoints {
+    /// Creates a step, which renames e.g. `the_kernel` to `{prefix}the_kernel`.
+    #[must_use]
+    pub fn new<S: Into<String>>(prefix: S) -> Self {
+        PrefixEntryPoints {
+            prefix: prefix.into(),
+        }
+    }
+}
+
+impl SetPtxVersion {
+    /// Creates a step, which sets the PTX ISA `version`.
+    #[must_use]
+    pub fn new<S: Into<String>>(version: S) -> Self {
+        SetPtxVersion {
+            version: version.into(),
+        }
+    }
+}
+
+impl PostProcess for StripComments {
+    fn process(&self, assembly: &str) -> Result<String> {
+        let mut processed = String::with_capacity(assembly.len());
+        let mut in_block_comment = false;
+
+        for line in assembly.lines() {
+            let mut code = String::with_capacity(line.len());
+            let mut in_string = false;
+            let mut chars = line.chars().peekable();
+
+            while let Some(c) = chars.next() {
+                if in_block_comment {
+                    if c == '*' && chars.peek() == Some(&'/') {
+                        chars.next();
+                        in_block_comment = false;
+                    }
+                } else if in_string {
+                    in_string = c != '"';
+                    code.push(c);
+                } else if c == '/' && chars.peek() == Some(&'/') {
+                    break;
+                } else if c == '/' && chars.peek() == Some(&'*') {
+                    chars.next();
+                    in_block_comment = true;
+                } else {
+                    in_string = c == '"';
+                    code.push(c);
+                }
+            }
+
+            // lines which only consisted of comments are dropped entirely
+            let code = code.trim_end();
+
+            if code.trim().is_empty() && !line.trim().is_empty() {
+                continue;
+            }
+
+            processed.push_str(code);
+            processed.push('\n');
+        }
+
+        Ok(processed)
+    }
+}
+
+impl PostProcess for StripDebugInfo {
+    fn process(&self, assembly: &str) -> Result<String> {
+        let mut processed = String::with_capacity(assembly.len());
+        let mut section_depth = None;
+
+        for line in assembly.lines() {
+            let code = line.trim();
+
+            if let Some(depth) = section_depth {
+                section_depth = match code {
+                    "{" => Some(depth + 1),
+                    "}" if depth <= 1 => None,
+                    "}" => Some(depth - 1),
+                    _ => Some(depth),
+                };
+
+                continue;
+            }
+
+            if code.starts_with(".section") && code.contains(".debug") {
+                section_depth = Some(0);
+                continue;
+            }
+
+            // `.loc` must not match e.g. the `.local` state space
+            let directive = code.split_whitespace().next().unwrap_or_default();
+
+            if directive == ".file" || directive == ".loc" {
+                continue;
+            }
+
+            // the `debug` target option requires the debug sections
+            if code.starts_with(".target") {
+                processed.push_str(&line.replace(", debug", ""));
+            } else {
+                processed.push_str(line);
+            }
+
+            processed.push('\n');
+        }
+
+        Ok(processed)
+    }
+}
+
+impl PostProcess for PrefixEntryPoints {
+    fn process(&self, assembly: &str) -> Result<String> {
+        static ENTRY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"(\.entry\s+)([A-Za-z_$%][\w$]*)").expect("Unable to parse regex...")
+        });
+
+        Ok(ENTRY_REGEX
+            .replace_all(assembly, |captures: &Captures| {
+                format!("{}{}{}", &captures[1], self.prefix, &captures[2])
+            })
+            .into_owned())
+    }
+}
+
+impl PostProcess for SetPtxVersion {
+    fn process(&self, assembly: &str) -> Result<String> {
+        static VERSION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"(?m)^(\s*\.version\s+)\S+").expect("Unable to parse regex...")
+        });
+
+        Ok(VERSION_REGEX
+            .replace(assembly, |captures: &Captures| {
+                format!("{}{}", &captures[1], self.version)
+            })
+            .into_owned())
+    }
+}
+
+#[test]
+fn should_strip_comments() {
+    let assembly = "\
+// Generated by LLVM NVPTX Back-End
+.version 7.5 // ISA
+.file 1 \"//src/lib.rs\"
+
+/* block
+   comment */ .visible .entry the_kernel()
+{
+\tret; /* inline */ // done
+}
+";
+
+    assert_eq!(
+        StripComments.process(assembly).unwrap(),
+        "\
+.version 7.5
+.file 1 \"//src/lib.rs\"
+
+ .visible .entry the_kernel()
+{
+\tret;
+}
+"
+    );
+}
+
+#[test]
+fn should_strip_debug_info() {
+    let assembly = "\
+.version 7.5
+.target sm_75, debug
+.address_size 64
+.visible .entry the_kernel()
+{
+\t.local .align 8 .b8 __local_depot0[16];
+\t.loc 1 5 0
+\tret;
+}
+\t.file 1 \"/tmp/src/lib.rs\"
+\t.section\t.debug_abbrev
+\t{
+.b8 1
+\t}
+\t.section\t.debug_info
+\t{
+.b32 52
+\t}
+";
+
+    assert_eq!(
+        StripDebugInfo.process(assembly).unwrap(),
+        "\
+.version 7.5
+.target sm_75
+.address_size 64
+.visible .entry the_kernel()
+{
+\t.local .align 8 .b8 __local_depot0[16];
+\tret;
+}
+"
+    );
+}
+
+#[test]
+fn should_rewrite_entry_points_and_version() {
+    let assembly = "\
+.version 7.5
+.visible .entry the_kernel(
+\t.param .u64 the_kernel_param_0
+)
+.visible .entry\tother_kernel()
+";
+
+    let processed = SetPtxVersion::new("8.0")
+        .process(&PrefixEntryPoints::new("lib_").process(assembly).unwrap())
+        .unwrap();
+
+    assert_eq!(
+        processed,
+        "\
+.version 8.0
+.visible .entry lib_the_kernel(
+\t.param .u64 the_kernel_param_0
+)
+.visible .entry\tlib_other_kernel()
+"
+    );
+}
@@ -1,10 +1,24 @@
-use std::{fmt, process::exit};
+use std::{
+    collections::BTreeSet,
+    fmt,
+    io::Write,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::{Arc, Condvar, LazyLock, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
 
 use colored::{control, Colorize};
+use regex::Regex;
+use serde_json::{json, Value};
 
 use crate::{
     builder::{BuildStatus, Builder},
-    error::{Error, Result},
+    embed::PtxSource,
+    error::{BuildErrorKind, Error, Result, ResultExt},
+    event::BuildEvent,
+    fatbin::Fatbin,
 };
 
 /// Cargo integration adapter.
@@ -24,6 +38,10 @@ use crate::{
 /// ```
 pub struct CargoAdapter {
     env_name: String,
+    progress_interval: Option<Duration>,
+    fatbin: Option<(String, Fatbin)>,
+    ptx_source: PtxSource,
+    ptx_source_path: Option<PathBuf>,
 }
 
 impl CargoAdapter {
@@ -39,9 +57,54 @@ impl CargoAdapter {
     pub fn with_env_var<S: AsRef<str>>(env_name: S) -> Self {
         CargoAdapter {
             env_name: env_name.as_ref().to_string(),
+            progress_interval: None,
+            fatbin: None,
+            ptx_source: PtxSource::new(),
+            ptx_source_path: None,
         }
     }
 
+    /// Reports the progress of the device build every `interval`, see
+    /// [`ProgressReporter`].
+    #[must_use]
+    pub fn with_progress(mut self, interval: Duration) -> Self {
+        self.progress_interval = Some(interval);
+        self
+    }
+
+    /// Packages the build output into `fatbin` and provides its path to Rust
+    /// via `env_name` environment variable, see [`Fatbin::with_output`].
+    ///
+    /// The fat binary can already contain the outputs of builds for other
+    /// architectures.
+    #[must_use]
+    pub fn with_fatbin<S: AsRef<str>>(mut self, env_name: S, fatbin: Fatbin) -> Self {
+        self.fatbin = Some((env_name.as_ref().to_string(), fatbin));
+        self
+    }
+
+    /// Writes a Rust source to `path`, which embeds the PTX assembly as a C
+    /// string, see [`PtxSource`].
+    ///
+    /// The host crate can then include it instead of the assembly path:
+    /// ```ignore
+    /// include!(concat!(env!("OUT_DIR"), "/ptx.rs"));
+    /// ```
+    #[must_use]
+    pub fn with_ptx_source<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.ptx_source_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Compresses the PTX assembly in the Rust source of
+    /// [`CargoAdapter::with_ptx_source`], see [`PtxSource::enable_compression`].
+    #[cfg(feature = "compression")]
+    #[must_use]
+    pub fn with_compression(mut self) -> Self {
+        self.ptx_source = self.ptx_source.enable_compression();
+        self
+    }
+
     /// Runs build process and reports artifacts to Cargo.
     ///
     /// Depends on whether the build was successful or not, will either
@@ -51,13 +114,22 @@ impl CargoAdapter {
         if let Err(error) = self.build_inner(&builder) {
             eprintln!("{}", ErrorLogPrinter::print(error));
             exit(1);
-        } else {
-            exit(0);
         }
+
+        exit(0);
     }
 
     fn build_inner(&self, builder: &Builder) -> Result<()> {
-        match builder.build()? {
+        let status = match self.progress_interval {
+            Some(interval) => {
+                let reporter = ProgressReporter::start(interval);
+                builder.build_with_events(|event| reporter.on_event(&event))?
+            }
+
+            None => builder.build()?,
+        };
+
+        match status {
             BuildStatus::Success(output) => {
                 let dependencies = output.dependencies()?;
 
@@ -67,6 +139,16 @@ impl CargoAdapter {
                     output.get_assembly_path().display()
                 );
 
+                if let Some((env_name, fatbin)) = &self.fatbin {
+                    let fatbin_path = fatbin.clone().with_output(&output)?.create()?;
+
+                    println!("cargo:rustc-env={}={}", env_name, fatbin_path.display());
+                }
+
+                if let Some(path) = &self.ptx_source_path {
+                    self.ptx_source.clone().with_output(&output)?.write(path)?;
+                }
+
                 for path in dependencies {
                     println!("cargo:rerun-if-changed={}", path.display());
                 }
@@ -74,13 +156,326 @@ impl CargoAdapter {
 
             BuildStatus::NotNeeded => {
                 println!("cargo:rustc-env={}=/dev/null", self.env_name);
+
+                if let Some((env_name, _)) = &self.fatbin {
+                    println!("cargo:rustc-env={env_name}=/dev/null");
+                }
+
+                if let Some(path) = &self.ptx_source_path {
+                    self.ptx_source.write(path)?;
+                }
             }
+        }
+
+        Ok(())
+    }
+}
+
+/// Periodic progress report of a device build.
+///
+/// Building `core` and `compiler_builtins` for the device can take minutes,
+/// while the host `cargo build` shows nothing. The reporter consumes the
+/// [`BuildEvent`]s and emits a `cargo:warning=` line with the number of
+/// compiled crates and the elapsed time every `interval`, until it is dropped.
+///
+/// There is no total, as `cargo` does not report the number of crates in the
+/// build ahead.
+///
+/// # Usage in `build.rs`
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use ptx_builder::{prelude::*, reporter::ProgressReporter};
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// let reporter = ProgressReporter::start(Duration::from_secs(10));
+///
+/// Builder::new(".")?.build_with_events(|event| reporter.on_event(&event))?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProgressReporter {
+    shared: Arc<(Mutex<Progress>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[derive(Debug)]
+struct Progress {
+    started: Instant,
+    compiled: BTreeSet<String>,
+    finished: bool,
+}
+
+impl ProgressReporter {
+    /// Starts to report the progress every `interval`.
+    #[must_use]
+    pub fn start(interval: Duration) -> Self {
+        let shared = Arc::new((Mutex::new(Progress::new()), Condvar::new()));
+
+        let thread = {
+            let shared = shared.clone();
+
+            thread::spawn(move || {
+                let (progress, finished) = &*shared;
+                let mut progress = progress.lock().unwrap_or_else(PoisonError::into_inner);
+
+                loop {
+                    progress = finished
+                        .wait_timeout_while(progress, interval, |progress| !progress.finished)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+
+                    if progress.finished {
+                        return;
+                    }
+
+                    println!("cargo:warning={}", progress.line());
+                }
+            })
         };
 
+        ProgressReporter {
+            shared,
+            thread: Some(thread),
+        }
+    }
+
+    /// Updates the progress with the build `event`.
+    pub fn on_event(&self, event: &BuildEvent) {
+        self.shared
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .on_event(event);
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        let (progress, finished) = &*self.shared;
+
+        progress
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .finished = true;
+        finished.notify_all();
+
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Progress {
+    fn new() -> Self {
+        Progress {
+            started: Instant::now(),
+            compiled: BTreeSet::new(),
+            finished: false,
+        }
+    }
+
+    fn on_event(&mut self, event: &BuildEvent) {
+        // a crate is reported once per artifact, e.g. `.rlib` and `.rmeta`
+        if let BuildEvent::ArtifactReady { package_id, .. } = event {
+            self.compiled.insert(package_id.clone());
+        }
+    }
+
+    fn line(&self) -> String {
+        let elapsed = self.started.elapsed().as_secs();
+
+        format!(
+            "Building device crate: {} crates compiled, {}m {:02}s elapsed",
+            self.compiled.len(),
+            elapsed / 60,
+            elapsed % 60
+        )
+    }
+}
+
+/// Timings and assembly size of a successful build, see
+/// [`BuildOutput::report`](crate::builder::BuildOutput::report).
+#[derive(Clone, Debug, PartialEq)]
+pub struct BuildReport {
+    /// Wall-clock duration of the `cargo` invocation, zero for cached builds.
+    pub duration: Duration,
+    /// Whether `cargo` was skipped, as the previous build was up to date.
+    pub cached: bool,
+    /// Compile times of the crates, which `cargo` has built.
+    pub crate_timings: Vec<CrateTiming>,
+    /// Size of the PTX assembly in bytes.
+    pub assembly_size: u64,
+    /// Number of lines of the PTX assembly.
+    pub assembly_lines: usize,
+    /// Number of kernel entry points in the PTX assembly.
+    pub kernel_count: usize,
+}
+
+/// Size of the PTX assembly before and after compression, see
+/// [`BuildOutput::compression_report`](crate::builder::BuildOutput::compression_report).
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionReport {
+    /// Size of the PTX assembly in bytes.
+    pub uncompressed_size: u64,
+    /// Size of the zlib-compressed PTX assembly in bytes, as it is embedded
+    /// with [`PtxSource::enable_compression`].
+    pub compressed_size: u64,
+}
+
+/// Compile time of a crate, as reported by `cargo --timings`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrateTiming {
+    /// Opaque `cargo` identifier of the package.
+    pub package_id: String,
+    /// Name of the compiled target, e.g. the library.
+    pub target: String,
+    /// Time spent compiling the target.
+    pub duration: Duration,
+}
+
+impl BuildReport {
+    /// Returns the report as a JSON value.
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({
+            "duration": self.duration.as_secs_f64(),
+            "cached": self.cached,
+            "crates": self
+                .crate_timings
+                .iter()
+                .map(|timing| json!({
+                    "package_id": timing.package_id,
+                    "target": timing.target,
+                    "duration": timing.duration.as_secs_f64(),
+                }))
+                .collect::<Vec<_>>(),
+            "assembly": {
+                "size": self.assembly_size,
+                "lines": self.assembly_lines,
+                "kernels": self.kernel_count,
+            },
+        })
+    }
+
+    /// Writes the report as a single line of JSON, e.g. for CI dashboards.
+    pub fn write_json<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "{}", self.to_json()).context(BuildErrorKind::OtherError)?;
+
         Ok(())
     }
 }
 
+impl CrateTiming {
+    /// Parses a `timing-info` JSON message, which `cargo` prints to stdout.
+    pub(crate) fn from_json_message(line: &str) -> Option<Self> {
+        let message = serde_json::from_str::<Value>(line).ok()?;
+
+        if message["reason"] != "timing-info" {
+            return None;
+        }
+
+        Some(CrateTiming {
+            package_id: String::from(message["package_id"].as_str()?),
+            target: String::from(message["target"]["name"].as_str()?),
+            duration: Duration::try_from_secs_f64(message["duration"].as_f64()?).ok()?,
+        })
+    }
+}
+
+/// Register and memory usage of a kernel, as reported by `ptxas -v`, see
+/// [`BuildOutput::get_kernel_usage`](crate::builder::BuildOutput::get_kernel_usage).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KernelUsage {
+    /// Name of the kernel entry point.
+    pub name: String,
+    /// Number of registers per thread.
+    pub registers: u32,
+    /// Size of the stack frame per thread in bytes.
+    pub stack_frame: u64,
+    /// Bytes stored to local memory, as the registers did not suffice.
+    pub spill_stores: u64,
+    /// Bytes loaded from local memory, as the registers did not suffice.
+    pub spill_loads: u64,
+    /// Static shared memory per block in bytes.
+    pub shared_memory: u64,
+    /// Constant memory in bytes, summed over all banks.
+    pub constant_memory: u64,
+}
+
+impl KernelUsage {
+    /// Parses the `-v` output of `ptxas`.
+    ///
+    /// Only kernel entry points are reported, the properties of the device
+    /// functions they call are skipped.
+    pub(crate) fn from_ptxas_output(output: &str) -> Vec<Self> {
+        static ENTRY_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"Compiling entry function '([^']+)'").expect("Unable to parse regex...")
+        });
+        static PROPERTIES_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"Function properties for (\S+)").expect("Unable to parse regex...")
+        });
+        static AMOUNT_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+            Regex::new(r"(\d+) (registers|bytes stack frame|bytes spill stores|bytes spill loads|bytes smem|bytes cmem\[\d+\])")
+                .expect("Unable to parse regex...")
+        });
+
+        let mut kernels: Vec<KernelUsage> = Vec::new();
+        let mut properties_of = None;
+
+        for line in output.lines() {
+            if let Some(captures) = ENTRY_REGEX.captures(line) {
+                kernels.push(KernelUsage {
+                    name: String::from(&captures[1]),
+                    ..KernelUsage::default()
+                });
+                continue;
+            }
+
+            if let Some(captures) = PROPERTIES_REGEX.captures(line) {
+                properties_of = Some(String::from(&captures[1]));
+                continue;
+            }
+
+            // the stack frame line belongs to the last function properties,
+            // while the `Used` line belongs to the last entry function
+            let kernel = if line.contains("stack frame") {
+                kernels
+                    .iter_mut()
+                    .rev()
+                    .find(|kernel| Some(&kernel.name) == properties_of.as_ref())
+            } else if line.contains("Used") {
+                kernels.last_mut()
+            } else {
+                None
+            };
+
+            let Some(kernel) = kernel else {
+                continue;
+            };
+
+            for captures in AMOUNT_REGEX.captures_iter(line) {
+                let Ok(amount) = captures[1].parse::<u64>() else {
+                    continue;
+                };
+
+                match &captures[2] {
+                    "registers" => kernel.registers = u32::try_from(amount).unwrap_or(u32::MAX),
+                    "bytes stack frame" => kernel.stack_frame = amount,
+                    "bytes spill stores" => kernel.spill_stores = amount,
+                    "bytes spill loads" => kernel.spill_loads = amount,
+                    "bytes smem" => kernel.shared_memory = amount,
+                    _ => kernel.constant_memory += amount,
+                }
+            }
+        }
+
+        kernels
+    }
+}
+
 /// Nice error log printer.
 ///
 /// ```no_run
@@ -147,3 +542,100 @@ impl fmt::Display for ErrorLogPrinter {
         Ok(())
     }
 }
+
+#[test]
+fn should_count_compiled_crates() {
+    let mut progress = Progress::new();
+
+    for package in ["core", "compiler_builtins"] {
+        progress.on_event(&BuildEvent::Compiling {
+            package: String::from(package),
+            version: String::from("0.0.0"),
+        });
+    }
+
+    for path in ["libcore.rlib", "libcore.rmeta"] {
+        progress.on_event(&BuildEvent::ArtifactReady {
+            package_id: String::from("core"),
+            path: path.into(),
+        });
+    }
+
+    progress.started -= Duration::from_secs(65);
+
+    assert_eq!(
+        progress.line(),
+        "Building device crate: 1 crates compiled, 1m 05s elapsed"
+    );
+}
+
+#[test]
+fn should_write_json_reports() {
+    let timing = CrateTiming::from_json_message(
+        r#"{"reason":"timing-info","package_id":"sample","target":{"kind":["cdylib"],"name":"sample_ptx_crate"},"mode":"build","duration":1.5,"rmeta_time":null}"#,
+    )
+    .unwrap();
+
+    assert_eq!(timing.target, "sample_ptx_crate");
+    assert_eq!(timing.duration, Duration::from_millis(1500));
+
+    let report = BuildReport {
+        duration: Duration::from_secs(2),
+        cached: false,
+        crate_timings: vec![timing],
+        assembly_size: 1024,
+        assembly_lines: 42,
+        kernel_count: 1,
+    };
+
+    let mut json = Vec::new();
+    report.write_json(&mut json).unwrap();
+
+    assert_eq!(
+        String::from_utf8(json).unwrap(),
+        concat!(
+            r#"{"assembly":{"kernels":1,"lines":42,"size":1024},"cached":false,"#,
+            r#""crates":[{"duration":1.5,"package_id":"sample","target":"sample_ptx_crate"}],"#,
+            r#""duration":2.0}"#,
+            "\n"
+        )
+    );
+}
+
+#[test]
+fn should_parse_kernel_usage() {
+    let output = "\
+ptxas info    : 0 bytes gmem
+ptxas info    : Function properties for helper
+    8 bytes stack frame, 0 bytes spill stores, 0 bytes spill loads
+ptxas info    : Compiling entry function 'add' for 'sm_75'
+ptxas info    : Function properties for add
+    0 bytes stack frame, 0 bytes spill stores, 0 bytes spill loads
+ptxas info    : Used 8 registers, 376 bytes cmem[0]
+ptxas info    : Compiling entry function 'reduce' for 'sm_75'
+ptxas info    : Function properties for reduce
+    24 bytes stack frame, 16 bytes spill stores, 12 bytes spill loads
+ptxas info    : Used 255 registers, used 1 barriers, 4096 bytes smem, 384 bytes cmem[0], 8 bytes cmem[2]
+";
+
+    assert_eq!(
+        KernelUsage::from_ptxas_output(output),
+        [
+            KernelUsage {
+                name: String::from("add"),
+                registers: 8,
+                constant_memory: 376,
+                ..KernelUsage::default()
+            },
+            KernelUsage {
+                name: String::from("reduce"),
+                registers: 255,
+                stack_frame: 24,
+                spill_stores: 16,
+                spill_loads: 12,
+                shared_memory: 4096,
+                constant_memory: 392,
+            },
+        ]
+    );
+}
@@ -1,30 +1,30 @@
 use std::{
-    collections::hash_map::DefaultHasher,
     env, fs,
-    hash::{Hash, Hasher},
     io::{BufReader, Read},
     path::{Path, PathBuf},
 };
 
 use crate::{
-    builder::CrateType,
+    builder::{CrateType, GpuTarget},
     error::{BuildErrorKind, Result, ResultExt},
+    metadata::Metadata,
 };
 
-#[derive(Hash, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub enum FilePrefix {
     Library(String),
     Binary(String),
     Mixed { lib: String, bin: String },
 }
 
-#[derive(Hash, Clone, Debug)]
+#[derive(Clone, Debug)]
 /// Information about CUDA crate.
 pub struct Crate {
     name: String,
     path: PathBuf,
     output_file_prefix: String,
     deps_file_prefix: FilePrefix,
+    metadata: Metadata,
 }
 
 impl Crate {
@@ -89,11 +89,14 @@ impl Crate {
             }
         };
 
+        let metadata = Metadata::from_cargo_toml(&cargo_toml)?;
+
         Ok(Crate {
             name: cargo_toml_name.to_string(),
             path,
             output_file_prefix,
             deps_file_prefix,
+            metadata,
         })
     }
 
@@ -123,11 +126,13 @@ impl Crate {
         }
     }
 
-    /// Returns the crate type to build the PTX with
-    pub fn get_crate_type(&self, crate_type: Option<CrateType>) -> Result<&str> {
+    /// Returns the crate type to build the device artifact with
+    pub fn get_crate_type(&self, crate_type: Option<CrateType>, gpu: GpuTarget) -> Result<&str> {
         match (&self.deps_file_prefix, crate_type) {
             (FilePrefix::Library(_), Some(CrateType::Library) | None)
-            | (FilePrefix::Mixed { .. }, Some(CrateType::Library)) => Ok("cdylib,rlib"),
+            | (FilePrefix::Mixed { .. }, Some(CrateType::Library)) => {
+                Ok(gpu.get_library_crate_type())
+            }
 
             (FilePrefix::Binary(_), Some(CrateType::Binary) | None)
             | (FilePrefix::Mixed { .. }, Some(CrateType::Binary)) => Ok("bin"),
@@ -151,28 +156,30 @@ impl Crate {
         &self.name
     }
 
+    /// Returns the `[package.metadata.ptx-builder]` configuration.
+    pub fn get_metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
     /// Returns crate root path.
     pub fn get_path(&self) -> &Path {
         self.path.as_path()
     }
 
-    /// Returns temporary crate build location that can be `cargo clean`ed.
-    pub fn get_output_path(&self) -> Result<PathBuf> {
-        let mut path = PathBuf::from(env!("OUT_DIR"));
+    /// Returns temporary crate build location inside `target_dir` that can be
+    /// `cargo clean`ed.
+    ///
+    /// The `fingerprint` of the build configuration keeps incompatible
+    /// builds of the same crate apart.
+    pub fn get_output_path(&self, target_dir: &Path, fingerprint: &str) -> Result<PathBuf> {
+        let mut path = target_dir.to_path_buf();
 
         path.push(&self.output_file_prefix);
-        path.push(format!("{:x}", self.get_hash()));
+        path.push(fingerprint);
 
         fs::create_dir_all(&path).context(BuildErrorKind::OtherError)?;
         Ok(path)
     }
-
-    fn get_hash(&self) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        self.hash(&mut hasher);
-
-        hasher.finish()
-    }
 }
 
 #[test]
@@ -232,6 +239,21 @@ fn should_find_mixed_crate_names() {
     }
 }
 
+#[test]
+fn should_read_crate_metadata() {
+    let source = Crate::analyse("tests/fixtures/configured-crate").unwrap();
+
+    assert_eq!(source.get_metadata().target_cpu.as_deref(), Some("sm_75"));
+    assert_eq!(
+        source.get_metadata().kernels,
+        Some(vec![String::from("the_kernel")])
+    );
+
+    let source = Crate::analyse("tests/fixtures/sample-crate").unwrap();
+
+    assert_eq!(source.get_metadata(), &Metadata::default());
+}
+
 #[test]
 fn should_check_existence_of_crate_path() {
     let result = Crate::analyse("tests/fixtures/non-existing-crate");
@@ -262,8 +284,12 @@ fn should_check_validity_of_crate_path() {
 fn should_provide_output_path() {
     let source_crate = Crate::analyse("tests/fixtures/sample-crate").unwrap();
 
-    assert!(source_crate
-        .get_output_path()
-        .unwrap()
-        .starts_with(Path::new(env!("OUT_DIR")).join("sample_ptx_crate")));
+    assert_eq!(
+        source_crate
+            .get_output_path(Path::new(env!("OUT_DIR")), "0123456789abcdef")
+            .unwrap(),
+        Path::new(env!("OUT_DIR"))
+            .join("sample_ptx_crate")
+            .join("0123456789abcdef")
+    );
 }
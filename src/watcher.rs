@@ -0,0 +1,238 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    builder::{BuildStatus, Builder},
+    error::Result,
+    executable::CancellationToken,
+    fresh::{stamp, Stamp},
+};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Rebuilds the device crate whenever its sources change.
+///
+/// The watcher polls the files reported by
+/// [`BuildOutput::dependencies`](crate::builder::BuildOutput::dependencies)
+/// of the last successful build, together with the `Cargo.toml` and `src`
+/// directory of the crate, so that new modules or a fixed build error are
+/// picked up as well. Bursts of changes, e.g. an editor saving several files,
+/// are debounced into a single rebuild.
+///
+/// # Usage
+/// ``` no_run
+/// use std::thread;
+///
+/// use ptx_builder::{prelude::*, watcher::Watcher};
+/// # use ptx_builder::error::Result;
+///
+/// # fn main() -> Result<()> {
+/// let token = CancellationToken::new();
+/// let watcher = Watcher::new(Builder::new(".")?).with_cancellation(token.clone());
+///
+/// let handle = thread::spawn(move || {
+///     watcher.watch(|result| match result {
+///         Ok(assembly_path) => println!("reloading {}", assembly_path.display()),
+///         Err(error) => eprintln!("{}", ErrorLogPrinter::print(error)),
+///     })
+/// });
+///
+/// // ... until the host app shuts down
+/// token.cancel();
+/// handle.join().unwrap();
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Watcher {
+    builder: Builder,
+    poll_interval: Duration,
+    debounce: Duration,
+    cancellation: CancellationToken,
+}
+
+/// Stamp of every watched file, or `None` if missing.
+type Snapshot = BTreeMap<PathBuf, Option<Stamp>>;
+
+impl Watcher {
+    /// Creates a watcher, which rebuilds the crate with the `builder`.
+    #[must_use]
+    pub fn new(builder: Builder) -> Self {
+        Watcher {
+            builder,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            debounce: DEFAULT_DEBOUNCE,
+            cancellation: CancellationToken::new(),
+        }
+    }
+
+    /// Set how often the files are checked for changes, defaults to 500ms.
+    #[must_use]
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Set how long the files have to stay unchanged before a rebuild,
+    /// defaults to 200ms.
+    #[must_use]
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Stop watching once the `token` is cancelled, aborting the build which
+    /// is running at the moment.
+    #[must_use]
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Self {
+        self.builder = self.builder.with_cancellation(token.clone());
+        self.cancellation = token;
+        self
+    }
+
+    /// Builds the crate, and then rebuilds it on every change until the
+    /// watcher is cancelled.
+    ///
+    /// `on_build` is invoked after every build with the path of the new PTX
+    /// assembly, or with the build error. Returns immediately if the build
+    /// is not needed, e.g. when called recursively from the device crate.
+    pub fn watch<F: FnMut(Result<PathBuf>)>(&self, mut on_build: F) {
+        let mut dependencies = BTreeSet::new();
+
+        loop {
+            let result = self.build(&mut dependencies);
+
+            // the build has failed only because it was aborted
+            if self.cancellation.is_cancelled() {
+                return;
+            }
+
+            match result {
+                Some(result) => on_build(result),
+                None => return,
+            }
+
+            if !self.wait_for_changes(&dependencies) {
+                return;
+            }
+        }
+    }
+
+    /// Returns `None` if the build is not needed.
+    fn build(&self, dependencies: &mut BTreeSet<PathBuf>) -> Option<Result<PathBuf>> {
+        let output = match self.builder.build() {
+            Ok(BuildStatus::Success(output)) => output,
+            Ok(BuildStatus::NotNeeded) => return None,
+            Err(error) => return Some(Err(error)),
+        };
+
+        // keep watching the previous dependencies if they are unavailable
+        match output.dependencies() {
+            Ok(new_dependencies) => *dependencies = new_dependencies.into_iter().collect(),
+            Err(error) => return Some(Err(error)),
+        }
+
+        Some(Ok(output.get_assembly_path()))
+    }
+
+    /// Blocks until the watched files have changed and settled, or returns
+    /// `false` when cancelled.
+    fn wait_for_changes(&self, dependencies: &BTreeSet<PathBuf>) -> bool {
+        let watched = || {
+            let mut files = dependencies.clone();
+            collect_crate_files(self.builder.get_crate_path(), &mut files);
+            snapshot(&files)
+        };
+
+        let last_build = watched();
+        let mut pending: Option<(Snapshot, Instant)> = None;
+
+        loop {
+            thread::sleep(match pending {
+                Some(_) => self.poll_interval.min(self.debounce),
+                None => self.poll_interval,
+            });
+
+            if self.cancellation.is_cancelled() {
+                return false;
+            }
+
+            let current = watched();
+
+            pending = match pending {
+                Some((previous, changed)) if previous == current => {
+                    if changed.elapsed() >= self.debounce {
+                        return true;
+                    }
+
+                    Some((previous, changed))
+                }
+
+                _ if current != last_build => Some((current, Instant::now())),
+                _ => None,
+            };
+        }
+    }
+}
+
+/// Adds the `Cargo.toml` and every file in the `src` directory of the crate.
+fn collect_crate_files(crate_path: &Path, files: &mut BTreeSet<PathBuf>) {
+    files.insert(crate_path.join("Cargo.toml"));
+
+    let mut directories = vec![crate_path.join("src")];
+
+    while let Some(directory) = directories.pop() {
+        let Ok(entries) = fs::read_dir(&directory) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => directories.push(entry.path()),
+                Ok(_) => {
+                    files.insert(entry.path());
+                }
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+fn snapshot(files: &BTreeSet<PathBuf>) -> Snapshot {
+    files
+        .iter()
+        .map(|path| (path.clone(), stamp(path)))
+        .collect()
+}
+
+#[test]
+fn should_detect_new_and_changed_files() {
+    let crate_path = Path::new(env!("OUT_DIR")).join("watched-files");
+    let _ = fs::remove_dir_all(&crate_path);
+    fs::create_dir_all(crate_path.join("src")).unwrap();
+
+    fs::write(crate_path.join("src").join("lib.rs"), "mod kernel;").unwrap();
+
+    let mut files = BTreeSet::new();
+    collect_crate_files(&crate_path, &mut files);
+    let before = snapshot(&files);
+
+    assert_eq!(before.get(&crate_path.join("Cargo.toml")), Some(&None));
+    assert!(before[&crate_path.join("src").join("lib.rs")].is_some());
+
+    fs::create_dir_all(crate_path.join("src").join("kernel")).unwrap();
+    fs::write(crate_path.join("src").join("kernel").join("mod.rs"), "").unwrap();
+
+    let mut files = BTreeSet::new();
+    collect_crate_files(&crate_path, &mut files);
+    let after = snapshot(&files);
+
+    assert_ne!(before, after);
+    assert!(after[&crate_path.join("src").join("kernel").join("mod.rs")].is_some());
+}
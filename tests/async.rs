@@ -0,0 +1,206 @@
+#![cfg(feature = "async")]
+
+use std::{
+    env::current_dir,
+    fs::{read_to_string, remove_dir_all},
+    future::poll_fn,
+    path::{Path, PathBuf},
+    pin::Pin,
+    process::Command,
+    time::Duration,
+};
+
+use futures_core::Stream;
+use semver::{Version, VersionReq};
+
+use ptx_builder::{
+    error::*,
+    executable::{Executable, ExecutableRunner},
+    prelude::*,
+};
+
+struct Script(&'static str);
+
+impl Executable for Script {
+    fn get_name(&self) -> String {
+        String::from("sh")
+    }
+
+    fn get_command(&self) -> Command {
+        let mut command = Command::new("sh");
+        command.args(["-c", self.0]);
+        command
+    }
+
+    fn get_verification_hint(&self) -> String {
+        String::from("Some useful hint")
+    }
+
+    fn get_version_hint(&self) -> String {
+        String::from("Some useful hint about version")
+    }
+
+    fn get_required_version(&self) -> Option<VersionReq> {
+        None
+    }
+
+    fn get_current_version(&self) -> Result<Version> {
+        Ok(Version::new(1, 0, 0))
+    }
+}
+
+async fn collect_lines(build: &mut AsyncBuild<'_>) -> Vec<OutputLine> {
+    let mut lines = Vec::new();
+
+    while let Some(line) = poll_fn(|cx| Pin::new(&mut *build).poll_next(cx)).await {
+        lines.push(line);
+    }
+
+    lines
+}
+
+fn missing_target_toolchain() -> Toolchain {
+    Toolchain::Path {
+        cargo: PathBuf::from("cargo"),
+        rustc: current_dir()
+            .unwrap()
+            .join("tests")
+            .join("fixtures")
+            .join("missing-target-toolchain")
+            .join("rustc"),
+    }
+}
+
+#[tokio::test]
+async fn should_stream_build_output() {
+    let target_dir = Path::new(env!("OUT_DIR")).join("async-target-dir");
+
+    remove_dir_all(&target_dir).unwrap_or_default();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_target_dir(&target_dir)
+        .disable_colors();
+
+    let mut build = builder.build_async();
+    let lines = collect_lines(&mut build).await;
+
+    assert!(lines
+        .iter()
+        .any(|line| matches!(line, OutputLine::Stderr(line) if line.contains("Compiling"))));
+
+    match build.status().await.unwrap() {
+        BuildStatus::Success(output) => {
+            let assembly = read_to_string(output.get_assembly_path()).unwrap();
+
+            assert!(output.get_assembly_path().starts_with(&target_dir));
+            assert!(assembly.contains(".visible .entry the_kernel("));
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[tokio::test]
+async fn should_check_target_before_building() {
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_toolchain(missing_target_toolchain())
+        .disable_colors();
+
+    let mut build = builder.build_async();
+
+    assert_eq!(collect_lines(&mut build).await, []);
+
+    match build.status().await.unwrap_err().kind() {
+        BuildErrorKind::TargetNotInstalled { target, .. } => {
+            assert_eq!(target, "nvptx64-nvidia-cuda");
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[tokio::test]
+async fn should_await_status_without_streaming() {
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_toolchain(missing_target_toolchain());
+
+    let status = tokio::spawn(async move { builder.build_async().status().await.is_err() });
+
+    assert!(status.await.unwrap());
+}
+
+#[tokio::test]
+async fn should_run_command_async() {
+    let mut stdout_lines = Vec::new();
+    let mut stderr_lines = Vec::new();
+
+    let output = ExecutableRunner::new(Script("echo a; echo b >&2; echo c"))
+        .run_async(
+            |line| stdout_lines.push(String::from(line)),
+            |line| stderr_lines.push(String::from(line)),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(output.stdout, "a\nc\n");
+    assert_eq!(output.stderr, "b\n");
+    assert_eq!(stdout_lines, ["a", "c"]);
+    assert_eq!(stderr_lines, ["b"]);
+}
+
+#[tokio::test]
+async fn should_time_out_async() {
+    let mut runner = ExecutableRunner::new(Script("echo started; sleep 30 & wait"));
+    runner.with_timeout(Duration::from_millis(300));
+
+    match runner
+        .run_async(|_line| (), |_line| ())
+        .await
+        .unwrap_err()
+        .kind()
+    {
+        BuildErrorKind::TimedOut { stdout, .. } => assert_eq!(stdout, "started\n"),
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn should_kill_process_tree_on_drop() {
+    let (pid_sender, pid_receiver) = std::sync::mpsc::channel();
+
+    let run = ExecutableRunner::new(Script("sleep 30 & echo $!; wait")).run_async(
+        |line| pid_sender.send(String::from(line)).unwrap(),
+        |_line| (),
+    );
+
+    assert!(tokio::time::timeout(Duration::from_millis(300), run)
+        .await
+        .is_err());
+
+    let pid = pid_receiver.recv().unwrap();
+
+    // a killed grandchild stays a zombie until `init` reaps it
+    let is_alive = || {
+        let output = Command::new("ps")
+            .args(["-o", "stat=", "-p", &pid])
+            .output()
+            .unwrap();
+
+        output.status.success() && !String::from_utf8_lossy(&output.stdout).starts_with('Z')
+    };
+
+    for _ in 0..50 {
+        if !is_alive() {
+            return;
+        }
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    unreachable!("the grandchild should be killed with the command");
+}
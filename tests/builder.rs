@@ -2,13 +2,20 @@ use std::{
     env::{self, current_dir},
     fs::{remove_dir_all, File},
     io::prelude::*,
+    iter,
     path::{Path, PathBuf},
     sync::LazyLock,
+    time::Duration,
 };
 
 use antidote::Mutex;
 
-use ptx_builder::{error::*, prelude::*};
+use ptx_builder::{
+    error::*,
+    postprocess::{PrefixEntryPoints, SetPtxVersion, StripComments},
+    prelude::*,
+    reporter::KernelUsage,
+};
 
 static ENV_MUTEX: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
 
@@ -31,6 +38,30 @@ fn should_provide_output_path() {
     }
 }
 
+#[test]
+fn should_build_in_custom_target_dir() {
+    let _lock = ENV_MUTEX.lock();
+    let target_dir = Path::new(env!("OUT_DIR")).join("custom-target-dir");
+
+    remove_dir_all(&target_dir).unwrap_or_default();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_target_dir(&target_dir);
+
+    match builder.disable_colors().build().unwrap() {
+        BuildStatus::Success(output) => {
+            assert!(output
+                .get_assembly_path()
+                .starts_with(target_dir.join("sample_ptx_crate")));
+
+            assert!(output.get_assembly_path().exists());
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
 #[test]
 fn should_write_assembly() {
     let _lock = ENV_MUTEX.lock();
@@ -131,6 +162,38 @@ fn should_handle_rebuild_without_changes() {
     }
 }
 
+#[test]
+fn should_skip_cargo_when_up_to_date() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .disable_colors();
+
+    let build_lines = |builder: &Builder| {
+        let mut lines = Vec::new();
+
+        match builder
+            .build_live(|_line| (), |line| lines.push(String::from(line)))
+            .unwrap()
+        {
+            BuildStatus::Success(output) => assert!(output.get_assembly_path().exists()),
+            BuildStatus::NotNeeded => unreachable!(),
+        }
+
+        lines
+    };
+
+    assert!(!build_lines(&builder).is_empty());
+    assert!(build_lines(&builder).is_empty());
+
+    let builder = builder.disable_fast_path();
+
+    assert!(!build_lines(&builder).is_empty());
+}
+
 #[test]
 fn should_write_assembly_in_debug_mode() {
     let _lock = ENV_MUTEX.lock();
@@ -165,6 +228,384 @@ fn should_write_assembly_in_debug_mode() {
     }
 }
 
+#[test]
+fn should_apply_crate_metadata() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/configured-crate").unwrap();
+
+    match builder.disable_colors().build().unwrap() {
+        BuildStatus::Success(output) => {
+            let mut assembly_contents = String::new();
+
+            File::open(output.get_assembly_path())
+                .unwrap()
+                .read_to_string(&mut assembly_contents)
+                .unwrap();
+
+            assert!(output
+                .get_assembly_path()
+                .to_string_lossy()
+                .contains("debug"));
+
+            assert!(assembly_contents.contains(".target sm_75"));
+            assert!(assembly_contents.contains(".visible .entry the_kernel("));
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[test]
+fn should_override_crate_metadata() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/configured-crate")
+        .unwrap()
+        .set_features(Vec::<String>::new())
+        .disable_colors();
+
+    match builder.build().unwrap_err().kind() {
+        BuildErrorKind::MissingKernels(kernels) => {
+            assert_eq!(kernels, &[String::from("the_kernel")]);
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_check_target_before_building() {
+    let _lock = ENV_MUTEX.lock();
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    let stub_path = current_dir()
+        .unwrap()
+        .join("tests")
+        .join("fixtures")
+        .join("missing-target-toolchain");
+
+    env::set_var(
+        "PATH",
+        env::join_paths(iter::once(stub_path).chain(env::split_paths(&path))).unwrap(),
+    );
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .disable_colors();
+
+    let output = builder.build();
+
+    env::set_var("PATH", path);
+
+    match output.unwrap_err().kind() {
+        BuildErrorKind::TargetNotInstalled { target, hint } => {
+            assert_eq!(target, "nvptx64-nvidia-cuda");
+            assert!(hint.contains("rustup target add nvptx64-nvidia-cuda"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_check_custom_target_before_building() {
+    let _lock = ENV_MUTEX.lock();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_target("tests/fixtures/nvptx64-custom.json")
+        .disable_colors();
+
+    match builder.build().unwrap_err().kind() {
+        BuildErrorKind::TargetNotInstalled { target, hint } => {
+            assert_eq!(target, "nvptx64-custom");
+            assert!(hint.contains("build_std"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_build_for_custom_target() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_target("tests/fixtures/nvptx64-custom.json")
+        .build_std(["core"])
+        .disable_colors();
+
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            let assembly_path = output.get_assembly_path();
+
+            assert!(assembly_path
+                .components()
+                .any(|component| component.as_os_str() == "nvptx64-custom"));
+            assert!(assembly_path.exists());
+            assert!(!output.dependencies().unwrap().is_empty());
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[test]
+fn should_check_target_cpu_of_gpu_target() {
+    let _lock = ENV_MUTEX.lock();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_target(GpuTarget::Amdgpu)
+        .disable_colors();
+
+    match builder.build().unwrap_err().kind() {
+        BuildErrorKind::InvalidTargetCpu {
+            target,
+            target_cpu,
+            hint,
+        } => {
+            assert_eq!(target, "amdgcn-amd-amdhsa");
+            assert_eq!(target_cpu, &None);
+            assert!(hint.contains("gfx90a"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .set_target_cpu("gfx90a")
+        .disable_colors();
+
+    match builder.build().unwrap_err().kind() {
+        BuildErrorKind::InvalidTargetCpu {
+            target, target_cpu, ..
+        } => {
+            assert_eq!(target, "nvptx64-nvidia-cuda");
+            assert_eq!(target_cpu.as_deref(), Some("gfx90a"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_check_ptxas_arch() {
+    let _lock = ENV_MUTEX.lock();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_ptxas(Ptxas::default(), "gfx90a")
+        .disable_colors();
+
+    match builder.build().unwrap_err().kind() {
+        BuildErrorKind::InvalidTargetCpu {
+            target,
+            target_cpu,
+            hint,
+        } => {
+            assert_eq!(target, "ptxas");
+            assert_eq!(target_cpu.as_deref(), Some("gfx90a"));
+            assert!(hint.contains("sm_"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_compile_cubin_with_ptxas() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let ptxas = Ptxas::new(
+        current_dir()
+            .unwrap()
+            .join("tests/fixtures/cuda-toolkit/ptxas"),
+    );
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .with_ptxas(ptxas, "sm_75")
+        .disable_colors();
+
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            let cubin_path = output.get_cubin_path().unwrap();
+
+            assert_eq!(
+                cubin_path,
+                output.get_assembly_path().with_extension("sm_75.cubin")
+            );
+            assert!(cubin_path.exists());
+
+            assert_eq!(
+                output.get_kernel_usage().unwrap(),
+                [KernelUsage {
+                    name: String::from("the_kernel"),
+                    registers: 8,
+                    constant_memory: 376,
+                    ..KernelUsage::default()
+                }]
+            );
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+
+    // the usage is still reported once `cargo` and `ptxas` are skipped
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            assert_eq!(output.get_kernel_usage().unwrap().len(), 1);
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[test]
+fn should_post_process_assembly() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .set_expected_kernels(["the_kernel"])
+        .with_post_process(StripComments)
+        .with_post_process(PrefixEntryPoints::new("sample_"))
+        .with_post_process(SetPtxVersion::new("8.0"))
+        .disable_colors();
+
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            let assembly_path = output.get_assembly_path();
+            assert!(assembly_path.to_string_lossy().ends_with(".processed.ptx"));
+
+            let mut assembly = String::new();
+
+            File::open(&assembly_path)
+                .unwrap()
+                .read_to_string(&mut assembly)
+                .unwrap();
+
+            assert!(assembly.contains(".version 8.0"));
+            assert!(assembly.contains(".entry sample_the_kernel("));
+            assert!(!assembly.contains("//"));
+
+            // the original assembly is left untouched next to it
+            assert!(assembly_path
+                .with_extension("")
+                .with_extension("ptx")
+                .exists());
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[test]
+fn should_build_for_amdgpu() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/amdgpu-crate")
+        .unwrap()
+        .with_target(GpuTarget::Amdgpu)
+        .set_target_cpu("gfx90a")
+        .build_std(["core"])
+        .disable_colors();
+
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            let artifact_path = output.get_assembly_path();
+
+            assert_eq!(artifact_path.extension(), Some("elf".as_ref()));
+            assert!(artifact_path
+                .components()
+                .any(|component| component.as_os_str() == "amdgcn-amd-amdhsa"));
+            assert_eq!(output.report().unwrap().kernel_count, 1);
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[test]
+fn should_check_rust_src_for_build_std() {
+    let _lock = ENV_MUTEX.lock();
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    let stub_path = current_dir()
+        .unwrap()
+        .join("tests")
+        .join("fixtures")
+        .join("missing-target-toolchain");
+
+    env::set_var(
+        "PATH",
+        env::join_paths(iter::once(stub_path).chain(env::split_paths(&path))).unwrap(),
+    );
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .build_std(["core"])
+        .disable_colors();
+
+    let output = builder.build();
+
+    env::set_var("PATH", path);
+
+    match output.unwrap_err().kind() {
+        BuildErrorKind::ComponentNotInstalled { component, hint } => {
+            assert_eq!(component, "rust-src");
+            assert!(hint.contains("rustup component add rust-src"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_build_std_from_source() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .build_std(["core"])
+        .set_target_cpu("sm_75")
+        .disable_colors();
+
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            let mut assembly_contents = String::new();
+
+            File::open(output.get_assembly_path())
+                .unwrap()
+                .read_to_string(&mut assembly_contents)
+                .unwrap();
+
+            assert!(assembly_contents.contains(".target sm_75"));
+            assert!(assembly_contents.contains(".visible .entry the_kernel("));
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
 #[test]
 fn should_report_about_build_failure() {
     let _lock = ENV_MUTEX.lock();
@@ -218,6 +659,138 @@ fn should_report_about_build_failure() {
     }
 }
 
+#[test]
+fn should_emit_build_events() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .disable_colors();
+
+    let mut events = Vec::new();
+
+    match builder
+        .build_with_events(|event| events.push(event))
+        .unwrap()
+    {
+        BuildStatus::Success(_) => {
+            assert!(events.contains(&BuildEvent::Compiling {
+                package: String::from("sample-ptx-crate"),
+                version: String::from("0.1.0"),
+            }));
+
+            assert!(events.iter().any(|event| matches!(
+                event,
+                BuildEvent::ArtifactReady { path, .. } if path.extension() == Some("ptx".as_ref())
+            )));
+
+            assert!(matches!(events.last(), Some(BuildEvent::Finished { .. })));
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+}
+
+#[test]
+fn should_emit_diagnostic_events_on_failure() {
+    let _lock = ENV_MUTEX.lock();
+
+    let builder = Builder::new("tests/fixtures/faulty-crate")
+        .unwrap()
+        .disable_colors();
+
+    let mut events = Vec::new();
+    let output = builder.build_with_events(|event| events.push(event));
+
+    assert!(events.iter().any(|event| matches!(
+        event,
+        BuildEvent::Diagnostic { level, message, rendered: Some(_) }
+            if level == "error" && message == "cannot find function `external_fn` in this scope"
+    )));
+
+    assert!(!events
+        .iter()
+        .any(|event| matches!(event, BuildEvent::Finished { .. })));
+
+    match output.unwrap_err().kind() {
+        BuildErrorKind::BuildFailed(diagnostics) => {
+            assert!(diagnostics.contains(&String::from(
+                "error[E0425]: cannot find function `external_fn` in this scope"
+            )));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_report_build_timings_and_size() {
+    let _lock = ENV_MUTEX.lock();
+
+    cleanup_temp_location();
+
+    let builder = Builder::new("tests/fixtures/sample-crate")
+        .unwrap()
+        .enable_crate_timings()
+        .disable_colors();
+
+    match builder.build_with_events(|_event| ()).unwrap() {
+        BuildStatus::Success(output) => {
+            let report = output.report().unwrap();
+
+            assert!(!report.cached);
+            assert!(report.duration > Duration::ZERO);
+            assert!(report
+                .crate_timings
+                .iter()
+                .any(|timing| timing.target == "sample_ptx_crate"));
+
+            assert_eq!(report.kernel_count, 1);
+            assert!(report.assembly_lines > 0);
+            assert!(report.assembly_size > 0);
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+
+    match builder.build().unwrap() {
+        BuildStatus::Success(output) => {
+            let report = output.report().unwrap();
+
+            assert!(report.cached);
+            assert_eq!(report.kernel_count, 1);
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+
+    cleanup_temp_location();
+
+    let mut stdout_lines = Vec::new();
+
+    // the human output should not be mixed with the JSON messages
+    match builder
+        .build_live(|line| stdout_lines.push(String::from(line)), |_line| ())
+        .unwrap()
+    {
+        BuildStatus::Success(output) => {
+            let report = output.report().unwrap();
+
+            assert!(!report.cached);
+            assert!(report
+                .crate_timings
+                .iter()
+                .any(|timing| timing.target == "sample_ptx_crate"));
+        }
+
+        BuildStatus::NotNeeded => unreachable!(),
+    }
+
+    assert!(stdout_lines.is_empty());
+}
+
 #[test]
 fn should_provide_crate_source_files() {
     let _lock = ENV_MUTEX.lock();
@@ -272,6 +845,8 @@ fn should_not_get_built_recursively() {
 
 fn cleanup_temp_location() {
     let crate_names = &[
+        "amdgpu_crate",
+        "configured_ptx_crate",
         "faulty_ptx_crate",
         "sample_app_ptx_crate",
         "sample_ptx_crate",
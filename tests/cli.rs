@@ -0,0 +1,90 @@
+#![cfg(feature = "cli")]
+
+use std::process::Command;
+
+fn cargo_ptx() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-ptx"));
+
+    command.arg("ptx").env("RUST_BACKTRACE", "0");
+    command
+}
+
+#[test]
+fn should_list_subcommands() {
+    let output = cargo_ptx().arg("--help").output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert!(output.status.success());
+    assert!(stdout.contains("Usage: cargo ptx <COMMAND>"));
+    assert!(stdout.contains("build"));
+    assert!(stdout.contains("deps"));
+}
+
+#[test]
+fn should_report_errors_in_cargo_style() {
+    let output = cargo_ptx()
+        .args(["build", "--no-color", "tests/fixtures/non-existing-crate"])
+        .output()
+        .unwrap();
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(stderr.starts_with("[PTX] Internal error: Unable to analyse source crate\n"));
+    assert!(stderr.contains("Invalid device crate path"));
+}
+
+#[test]
+fn should_report_errors_as_json() {
+    let output = cargo_ptx()
+        .args([
+            "deps",
+            "--message-format",
+            "json",
+            "tests/fixtures/non-existing-crate",
+        ])
+        .output()
+        .unwrap();
+
+    let message: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(message["reason"], "error");
+    assert!(message["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("[PTX] Internal error: Unable to analyse source crate"));
+}
+
+#[test]
+fn should_reject_conflicting_profiles() {
+    let output = cargo_ptx()
+        .args(["build", "--release", "--profile", "debug"])
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn should_report_doctor_checks_as_json() {
+    let output = cargo_ptx()
+        .args([
+            "doctor",
+            "--message-format",
+            "json",
+            "tests/fixtures/sample-crate",
+        ])
+        .output()
+        .unwrap();
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let healthy = report["healthy"].as_bool().unwrap();
+
+    assert_eq!(output.status.code(), Some(i32::from(!healthy)));
+    assert_eq!(report["reason"], "doctor");
+    assert_eq!(report["checks"].as_array().unwrap().len(), 5);
+    assert_eq!(report["checks"][0]["check"], "cargo version");
+    assert_eq!(report["checks"][0]["passed"], true);
+}
@@ -0,0 +1,85 @@
+use std::env;
+
+use ptx_builder::{
+    doctor::{CheckKind, CheckStatus, Doctor},
+    executable::Toolchain,
+};
+
+#[test]
+fn should_run_toolchain_checks() {
+    let report = Doctor::new().run();
+
+    assert_eq!(
+        report
+            .checks()
+            .iter()
+            .map(|check| check.kind())
+            .collect::<Vec<_>>(),
+        &[
+            CheckKind::Cargo,
+            CheckKind::NightlyToolchain,
+            CheckKind::Target,
+            CheckKind::BitcodeLinker,
+        ]
+    );
+
+    assert!(report.get(CheckKind::Cargo).unwrap().is_passed());
+    assert!(report.get(CheckKind::LinkerFlavor).is_none());
+}
+
+#[test]
+fn should_find_linker_flavor_config() {
+    let report = Doctor::new()
+        .with_crate("tests/fixtures/sample-crate")
+        .run();
+
+    match report.get(CheckKind::LinkerFlavor).unwrap().status() {
+        CheckStatus::Passed(config) => {
+            assert!(config.ends_with("sample-crate/.cargo/config.toml"));
+        }
+
+        CheckStatus::Failed { .. } => unreachable!("the fixture config should be found"),
+    }
+}
+
+#[test]
+fn should_report_missing_linker_flavor_config() {
+    let report = Doctor::new().with_crate(env::temp_dir()).run();
+
+    match report.get(CheckKind::LinkerFlavor).unwrap().status() {
+        CheckStatus::Failed { hint, .. } => {
+            assert!(hint.contains("-Clinker-flavor=llbc"));
+        }
+
+        CheckStatus::Passed(_) => unreachable!("it should fail with proper hint"),
+    }
+
+    assert!(!report.is_healthy());
+}
+
+#[test]
+fn should_check_selected_toolchain() {
+    let path = env::current_dir()
+        .unwrap()
+        .join("tests")
+        .join("fixtures")
+        .join("stable-toolchain");
+
+    let report = Doctor::new()
+        .with_toolchain(Toolchain::Path {
+            cargo: path.join("cargo"),
+            rustc: path.join("rustc"),
+        })
+        .run();
+
+    match report.get(CheckKind::NightlyToolchain).unwrap().status() {
+        CheckStatus::Failed { reason, .. } => {
+            assert_eq!(
+                reason,
+                "'rustc 1.79.0 (129f3b996 2024-06-10)' is not a nightly toolchain"
+            );
+        }
+
+        CheckStatus::Passed(_) => unreachable!("the stub toolchain is stable"),
+    }
+}
@@ -0,0 +1,146 @@
+use std::{env, ffi::OsString, path::PathBuf, sync::LazyLock};
+
+use antidote::{Mutex, MutexGuard};
+use ptx_builder::{
+    environment::EnvPolicy,
+    executable::{CargoWith, ExecutableRunner, Toolchain},
+};
+
+const BUILD_SCRIPT_ENV: &[(&str, &str)] = &[
+    ("TARGET", "x86_64-unknown-linux-gnu"),
+    ("HOST", "x86_64-unknown-linux-gnu"),
+    ("OUT_DIR", "/host/target/debug/build/host-crate/out"),
+    ("RUSTC", "/host/bin/rustc"),
+    ("RUSTC_WRAPPER", "sccache"),
+    ("CARGO_ENCODED_RUSTFLAGS", "-Ctarget-cpu=native"),
+    ("CARGO_TARGET_DIR", "/host/target"),
+    ("CARGO_CFG_TARGET_OS", "linux"),
+    ("CARGO_FEATURE_DEFAULT", "1"),
+    ("CARGO_PKG_NAME", "host-crate"),
+    ("HOST_CRATE_SETTING", "1"),
+];
+
+static ENV_MUTEX: LazyLock<Mutex<()>> = LazyLock::new(|| Mutex::new(()));
+
+/// Restores the previous environment of the process on drop.
+struct EnvGuard {
+    saved: Vec<(&'static str, Option<OsString>)>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl Drop for EnvGuard {
+    fn drop(&mut self) {
+        for (key, val) in self.saved.drain(..) {
+            match val {
+                Some(val) => env::set_var(key, val),
+                None => env::remove_var(key),
+            }
+        }
+    }
+}
+
+fn simulate_build_script_env() -> EnvGuard {
+    let lock = ENV_MUTEX.lock();
+    let mut saved = Vec::with_capacity(BUILD_SCRIPT_ENV.len());
+
+    for (key, val) in BUILD_SCRIPT_ENV {
+        saved.push((*key, env::var_os(key)));
+        env::set_var(key, val);
+    }
+
+    EnvGuard { saved, _lock: lock }
+}
+
+fn stable_toolchain() -> Toolchain {
+    let path = env::current_dir()
+        .unwrap()
+        .join("tests")
+        .join("fixtures")
+        .join("stable-toolchain");
+
+    Toolchain::Path {
+        cargo: path.join("cargo"),
+        rustc: path.join("rustc"),
+    }
+}
+
+fn inherited_env(runner: &mut ExecutableRunner<CargoWith>) -> Vec<String> {
+    runner
+        .with_args(["print-env"])
+        .run()
+        .unwrap()
+        .stdout
+        .lines()
+        .filter_map(|line| line.split_once('=').map(|(key, _)| key.to_string()))
+        .collect()
+}
+
+#[test]
+fn should_match_names_and_prefixes() {
+    let policy = EnvPolicy::new();
+
+    assert!(policy.is_denied("TARGET"));
+    assert!(policy.is_denied("CARGO_CFG_TARGET_OS"));
+    assert!(policy.is_denied("CARGO_ENCODED_RUSTFLAGS"));
+    assert!(!policy.is_denied("TARGET_DIR"));
+    assert!(!policy.is_denied("CARGO_HOME"));
+    assert!(!policy.is_denied("PATH"));
+
+    assert!(!EnvPolicy::inherit_all().is_denied("TARGET"));
+    assert!(!policy.allow("CARGO_CFG_*").is_denied("CARGO_CFG_TARGET_OS"));
+}
+
+#[test]
+fn should_strip_build_script_env() {
+    let _env = simulate_build_script_env();
+
+    let env = inherited_env(
+        ExecutableRunner::new(CargoWith::new(stable_toolchain()))
+            .with_env_policy(&EnvPolicy::new())
+            .with_env("CARGO_TARGET_DIR", "/device/target"),
+    );
+
+    for key in [
+        "TARGET",
+        "HOST",
+        "OUT_DIR",
+        "RUSTC_WRAPPER",
+        "CARGO_ENCODED_RUSTFLAGS",
+        "CARGO_CFG_TARGET_OS",
+        "CARGO_FEATURE_DEFAULT",
+        "CARGO_PKG_NAME",
+    ] {
+        assert!(!env.iter().any(|k| k == key), "{key} should be stripped");
+    }
+
+    assert!(env.iter().any(|k| k == "CARGO_TARGET_DIR"));
+    assert!(env.iter().any(|k| k == "HOST_CRATE_SETTING"));
+    assert!(env.iter().any(|k| k == "PATH"));
+}
+
+#[test]
+fn should_apply_custom_policy() {
+    let _env = simulate_build_script_env();
+
+    let env = inherited_env(
+        ExecutableRunner::new(CargoWith::new(stable_toolchain()))
+            .with_env_policy(&EnvPolicy::new().allow("RUSTC_WRAPPER").deny("HOST_CRATE_*")),
+    );
+
+    assert!(env.iter().any(|k| k == "RUSTC_WRAPPER"));
+    assert!(!env.iter().any(|k| k == "HOST_CRATE_SETTING"));
+    assert!(!env.iter().any(|k| k == "TARGET"));
+}
+
+#[test]
+fn should_keep_explicit_toolchain_rustc() {
+    let _env = simulate_build_script_env();
+
+    let output = ExecutableRunner::new(CargoWith::new(stable_toolchain()))
+        .with_env_policy(&EnvPolicy::new())
+        .with_args(["print-rustc"])
+        .run()
+        .unwrap();
+
+    assert!(PathBuf::from(output.stdout.trim()).ends_with("stable-toolchain/rustc"));
+}
@@ -1,8 +1,20 @@
-use semver::VersionReq;
+use std::{
+    env,
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use semver::{Version, VersionReq};
 
 use ptx_builder::{
     error::*,
-    executable::{Cargo, Executable, ExecutableRunner},
+    executable::{
+        CancellationToken, Cargo, CargoWith, Executable, ExecutableRunner, Fatbinary, Ptxas, Rustc,
+        Toolchain,
+    },
 };
 
 mod cargo {
@@ -44,6 +56,130 @@ mod cargo {
     }
 }
 
+mod toolchain {
+    use super::*;
+
+    fn stable_toolchain_path() -> PathBuf {
+        env::current_dir()
+            .unwrap()
+            .join("tests")
+            .join("fixtures")
+            .join("stable-toolchain")
+    }
+
+    fn stable_toolchain() -> Toolchain {
+        let path = stable_toolchain_path();
+
+        Toolchain::Path {
+            cargo: path.join("cargo"),
+            rustc: path.join("rustc"),
+        }
+    }
+
+    #[test]
+    fn should_check_version_of_explicit_toolchain() {
+        let cargo = CargoWith::new(stable_toolchain());
+
+        assert_eq!(
+            cargo.get_current_version().unwrap(),
+            Version::parse("1.79.0").unwrap()
+        );
+
+        let rustc = Rustc::new(stable_toolchain());
+
+        assert_eq!(
+            rustc.get_current_version().unwrap(),
+            Version::parse("1.79.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn should_resolve_explicit_toolchain() {
+        let cargo = CargoWith::new(stable_toolchain());
+
+        assert_eq!(
+            cargo.resolve_path().unwrap(),
+            stable_toolchain_path()
+                .join("cargo")
+                .canonicalize()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn should_pass_explicit_rustc_to_cargo() {
+        let output = ExecutableRunner::new(CargoWith::new(stable_toolchain()))
+            .with_args(["print-rustc"])
+            .run()
+            .unwrap();
+
+        assert!(PathBuf::from(output.stdout.trim()).ends_with("stable-toolchain/rustc"));
+    }
+
+    #[test]
+    fn should_select_rustup_toolchain() {
+        let output = ExecutableRunner::new(Rustc::new(Toolchain::from("nightly")))
+            .with_args(["-V"])
+            .run()
+            .unwrap();
+
+        assert!(output.stdout.contains("-nightly"));
+    }
+}
+
+mod cuda_toolkit {
+    use super::*;
+
+    fn stub_ptxas() -> Ptxas {
+        Ptxas::new(
+            env::current_dir()
+                .unwrap()
+                .join("tests/fixtures/cuda-toolkit/ptxas"),
+        )
+    }
+
+    #[test]
+    fn should_check_ptxas_version() {
+        assert_eq!(
+            stub_ptxas().get_current_version().unwrap(),
+            Version::parse("12.4.131").unwrap()
+        );
+    }
+
+    #[test]
+    fn should_check_fatbinary_version() {
+        let fatbinary = Fatbinary::new(
+            env::current_dir()
+                .unwrap()
+                .join("tests/fixtures/cuda-toolkit/fatbinary"),
+        );
+
+        assert_eq!(
+            fatbinary.get_current_version().unwrap(),
+            Version::parse("12.4.131").unwrap()
+        );
+    }
+
+    #[test]
+    fn should_compile_cubin() {
+        let ptx_path = Path::new(env!("OUT_DIR")).join("stub-kernel.ptx");
+        let cubin_path = Path::new(env!("OUT_DIR")).join("stub-kernel.cubin");
+
+        write(&ptx_path, ".visible .entry the_kernel(\n)\n{\n\tret;\n}\n").unwrap();
+
+        let output = ExecutableRunner::new(stub_ptxas())
+            .with_args(["-v", "--gpu-name", "sm_75", "--output-file"])
+            .with_args([&cubin_path, &ptx_path])
+            .run()
+            .unwrap();
+
+        assert!(output
+            .stdout
+            .contains("Compiling entry function 'the_kernel' for 'sm_75'"));
+        assert!(read_to_string(cubin_path).unwrap().starts_with("cubin of"));
+    }
+}
+
 mod non_existing_command {
     use super::*;
 
@@ -67,6 +203,11 @@ mod non_existing_command {
         }
     }
 
+    #[test]
+    fn should_not_resolve_path() {
+        assert_eq!(NonExistingCommand.resolve_path(), None);
+    }
+
     #[test]
     fn should_not_provide_output() {
         let output = ExecutableRunner::new(NonExistingCommand).run();
@@ -125,3 +266,142 @@ mod unrealistic_version_requirement {
         }
     }
 }
+
+mod no_version_requirement {
+    use super::*;
+
+    struct UnversionedCommand;
+
+    impl Executable for UnversionedCommand {
+        fn get_name(&self) -> String {
+            String::from("echo")
+        }
+
+        fn get_verification_hint(&self) -> String {
+            String::from("Some useful hint")
+        }
+
+        fn get_version_hint(&self) -> String {
+            String::from("Some useful hint about version")
+        }
+
+        fn get_required_version(&self) -> Option<VersionReq> {
+            None
+        }
+
+        fn get_current_version(&self) -> Result<Version> {
+            unreachable!("the version should not be queried")
+        }
+    }
+
+    #[test]
+    fn should_not_check_version() {
+        let output = ExecutableRunner::new(UnversionedCommand)
+            .with_args(["unversioned"])
+            .run()
+            .unwrap();
+
+        assert_eq!(output.stdout, "unversioned\n");
+    }
+}
+
+mod interruption {
+    use super::*;
+
+    /// Prints a line and then hangs in a grandchild process, like `cargo`
+    /// waiting for `rustc`.
+    struct HangingCommand;
+
+    impl Executable for HangingCommand {
+        fn get_name(&self) -> String {
+            String::from("sh")
+        }
+
+        fn get_command(&self) -> Command {
+            let mut command = Command::new("sh");
+            command.args(["-c", "echo started; echo compiling >&2; sleep 30 & wait"]);
+            command
+        }
+
+        fn get_verification_hint(&self) -> String {
+            String::from("Some useful hint")
+        }
+
+        fn get_version_hint(&self) -> String {
+            String::from("Some useful hint about version")
+        }
+
+        fn get_required_version(&self) -> Option<VersionReq> {
+            None
+        }
+
+        fn get_current_version(&self) -> Result<Version> {
+            Ok(Version::new(1, 0, 0))
+        }
+    }
+
+    #[test]
+    fn should_time_out() {
+        let started = Instant::now();
+
+        let output = ExecutableRunner::new(HangingCommand)
+            .with_timeout(Duration::from_millis(300))
+            .run_live(|_line| (), |_line| ());
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        match output.unwrap_err().kind() {
+            BuildErrorKind::TimedOut {
+                command,
+                timeout,
+                stdout,
+                stderr,
+            } => {
+                assert_eq!(command, "sh");
+                assert_eq!(*timeout, Duration::from_millis(300));
+                assert_eq!(stdout, "started\n");
+                assert_eq!(stderr, "compiling\n");
+            }
+
+            _ => unreachable!("it should fail with proper error"),
+        }
+    }
+
+    #[test]
+    fn should_be_cancelled() {
+        let token = CancellationToken::new();
+        let started = Instant::now();
+
+        let canceller = {
+            let token = token.clone();
+
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(300));
+                token.cancel();
+            })
+        };
+
+        let mut lines = Vec::new();
+
+        let output = ExecutableRunner::new(HangingCommand)
+            .with_timeout(Duration::from_secs(60))
+            .with_cancellation(&token)
+            .run_live(|line| lines.push(String::from(line)), |_line| ());
+
+        canceller.join().unwrap();
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert_eq!(lines, [String::from("started")]);
+
+        match output.unwrap_err().kind() {
+            BuildErrorKind::Cancelled {
+                command, stdout, ..
+            } => {
+                assert_eq!(command, "sh");
+                assert_eq!(stdout, "started\n");
+            }
+
+            _ => unreachable!("it should fail with proper error"),
+        }
+    }
+}
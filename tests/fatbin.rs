@@ -0,0 +1,92 @@
+use std::{
+    env::current_dir,
+    fs::{read_to_string, write},
+    path::Path,
+};
+
+use ptx_builder::{error::*, executable::Fatbinary, fatbin::Fatbin};
+
+fn stub_fatbinary() -> Fatbinary {
+    Fatbinary::new(
+        current_dir()
+            .unwrap()
+            .join("tests/fixtures/cuda-toolkit/fatbinary"),
+    )
+}
+
+#[test]
+fn should_package_images() {
+    let out_dir = Path::new(env!("OUT_DIR"));
+
+    write(out_dir.join("kernels.sm_75.cubin"), "cubin").unwrap();
+    write(out_dir.join("kernels.sm_86.cubin"), "cubin").unwrap();
+    write(out_dir.join("kernels.ptx"), ".target sm_86\n").unwrap();
+
+    let fatbin_path = Fatbin::new(stub_fatbinary(), out_dir.join("kernels.fatbin"))
+        .with_cubin("sm_75", out_dir.join("kernels.sm_75.cubin"))
+        .with_cubin("sm_86", out_dir.join("kernels.sm_86.cubin"))
+        .with_ptx("sm_86", out_dir.join("kernels.ptx"))
+        .create()
+        .unwrap();
+
+    assert_eq!(fatbin_path, out_dir.join("kernels.fatbin"));
+
+    assert_eq!(
+        read_to_string(fatbin_path)
+            .unwrap()
+            .lines()
+            .collect::<Vec<_>>(),
+        [
+            format!("--create={}", out_dir.join("kernels.fatbin").display()),
+            String::from("-64"),
+            format!(
+                "--image=profile=sm_75,file={}",
+                out_dir.join("kernels.sm_75.cubin").display()
+            ),
+            format!(
+                "--image=profile=sm_86,file={}",
+                out_dir.join("kernels.sm_86.cubin").display()
+            ),
+            format!(
+                "--image=profile=compute_86,file={}",
+                out_dir.join("kernels.ptx").display()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn should_check_image_archs() {
+    let fatbin = Fatbin::new(
+        stub_fatbinary(),
+        Path::new(env!("OUT_DIR")).join("invalid.fatbin"),
+    )
+    .with_cubin("gfx90a", "kernels.cubin");
+
+    match fatbin.create().unwrap_err().kind() {
+        BuildErrorKind::InvalidTargetCpu {
+            target, target_cpu, ..
+        } => {
+            assert_eq!(target, "fatbinary");
+            assert_eq!(target_cpu.as_deref(), Some("gfx90a"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
+
+#[test]
+fn should_not_package_nothing() {
+    let fatbin = Fatbin::new(
+        stub_fatbinary(),
+        Path::new(env!("OUT_DIR")).join("empty.fatbin"),
+    );
+
+    match fatbin.create().unwrap_err().kind() {
+        BuildErrorKind::InternalError(message) => {
+            assert!(message.contains("without any cubin or PTX assembly"));
+        }
+
+        _ => unreachable!("it should fail with proper error"),
+    }
+}
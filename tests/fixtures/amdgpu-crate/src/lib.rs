@@ -0,0 +1,12 @@
+#![feature(abi_gpu_kernel)]
+#![no_std]
+
+#[no_mangle]
+pub unsafe extern "gpu-kernel" fn the_kernel(x: *const f64, y: *mut f64, a: f64) {
+    *y.offset(0) = *x.offset(0) * a;
+}
+
+#[panic_handler]
+fn panic(_: &::core::panic::PanicInfo) -> ! {
+    loop {}
+}
@@ -0,0 +1,158 @@
+use std::{
+    env::current_dir,
+    fs::{copy, create_dir_all, remove_dir_all, write, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ptx_builder::{error::*, prelude::*, watcher::Watcher};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn watched_crate(name: &str) -> PathBuf {
+    let source = Path::new("tests/fixtures/sample-crate");
+    let path = Path::new(env!("OUT_DIR")).join(name);
+
+    remove_dir_all(&path).unwrap_or_default();
+    create_dir_all(path.join("src")).unwrap();
+
+    for file in ["Cargo.toml", "src/lib.rs", "src/mod1.rs", "src/mod2.rs"] {
+        copy(source.join(file), path.join(file)).unwrap();
+    }
+
+    path
+}
+
+fn missing_target_toolchain() -> Toolchain {
+    Toolchain::Path {
+        cargo: PathBuf::from("cargo"),
+        rustc: current_dir()
+            .unwrap()
+            .join("tests")
+            .join("fixtures")
+            .join("missing-target-toolchain")
+            .join("rustc"),
+    }
+}
+
+/// Copies the stubs of a toolchain, whose `cargo` never finishes, next to
+/// a sysroot with the target and the linker.
+fn hanging_toolchain() -> Toolchain {
+    let source = current_dir()
+        .unwrap()
+        .join("tests")
+        .join("fixtures")
+        .join("hanging-toolchain");
+    let path = Path::new(env!("OUT_DIR")).join("hanging-toolchain");
+    let rustlib = path.join("sysroot").join("lib").join("rustlib");
+
+    remove_dir_all(&path).unwrap_or_default();
+    create_dir_all(rustlib.join("nvptx64-nvidia-cuda").join("lib")).unwrap();
+    create_dir_all(rustlib.join("stub-host").join("bin")).unwrap();
+
+    write(
+        rustlib
+            .join("nvptx64-nvidia-cuda")
+            .join("lib")
+            .join("libcore-stub.rlib"),
+        "",
+    )
+    .unwrap();
+    write(
+        rustlib
+            .join("stub-host")
+            .join("bin")
+            .join("llvm-bitcode-linker"),
+        "",
+    )
+    .unwrap();
+
+    for file in ["cargo", "rustc"] {
+        copy(source.join(file), path.join(file)).unwrap();
+    }
+
+    Toolchain::Path {
+        cargo: path.join("cargo"),
+        rustc: path.join("rustc"),
+    }
+}
+
+#[test]
+fn should_rebuild_on_source_changes() {
+    let crate_path = watched_crate("watched-crate");
+    let token = CancellationToken::new();
+
+    let watcher = Watcher::new(
+        Builder::new(&crate_path)
+            .unwrap()
+            .with_toolchain(missing_target_toolchain()),
+    )
+    .with_poll_interval(Duration::from_millis(20))
+    .with_debounce(Duration::from_millis(50))
+    .with_cancellation(token.clone());
+
+    let (sender, results) = mpsc::channel();
+
+    let handle = thread::spawn(move || {
+        watcher.watch(|result| {
+            sender
+                .send(result.map_err(|error| error.kind().clone()))
+                .unwrap()
+        });
+    });
+
+    let is_target_missing = |result: Result<_, BuildErrorKind>| {
+        matches!(result, Err(BuildErrorKind::TargetNotInstalled { .. }))
+    };
+
+    assert!(is_target_missing(results.recv_timeout(TIMEOUT).unwrap()));
+
+    // nothing has changed yet
+    assert!(results.recv_timeout(Duration::from_millis(300)).is_err());
+
+    OpenOptions::new()
+        .append(true)
+        .open(crate_path.join("src").join("mod1.rs"))
+        .unwrap()
+        .write_all(b"\n// changed\n")
+        .unwrap();
+
+    assert!(is_target_missing(results.recv_timeout(TIMEOUT).unwrap()));
+
+    token.cancel();
+    handle.join().unwrap();
+}
+
+#[test]
+fn should_abort_running_build_on_cancellation() {
+    let crate_path = watched_crate("cancelled-crate");
+    let token = CancellationToken::new();
+
+    let watcher = Watcher::new(
+        Builder::new(&crate_path)
+            .unwrap()
+            .with_toolchain(hanging_toolchain())
+            .with_target_dir(Path::new(env!("OUT_DIR")).join("hanging-target-dir"))
+            .disable_fast_path(),
+    )
+    .with_cancellation(token.clone());
+
+    let (sender, finished) = mpsc::channel();
+    let started = Instant::now();
+
+    thread::spawn(move || {
+        let mut results = 0;
+        watcher.watch(|_result| results += 1);
+        sender.send(results).unwrap();
+    });
+
+    thread::sleep(Duration::from_millis(300));
+    token.cancel();
+
+    // the aborted build is not reported
+    assert_eq!(finished.recv_timeout(TIMEOUT).unwrap(), 0);
+    assert!(started.elapsed() < TIMEOUT);
+}